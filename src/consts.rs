@@ -68,6 +68,7 @@ pub const FIRST_RANK: u64 = 0x00000000000000FFu64;
 pub const FIRST_RANK_NE: u64 = 0x000000000000007Eu64; // NO EDGES
 pub const A_FILE: u64 = 0x0101010101010101u64;
 pub const A_FILE_NE: u64 = 0x0001010101010100u64; // NO EDGES
+pub const H_FILE: u64 = A_FILE << 7;
 pub const A1_H8: u64 = 0x8040201008040201u64;
 pub const A8_H1: u64 = 0x0102040810204080u64;
 pub const LRT: u64 = 0x0080C0E0F0F8FCFEu64; // i > j
@@ -115,6 +116,72 @@ pub const BKR_CASTLE: usize = BK_KS_CASTLE - 1;
 pub const BQR_START: usize = BK_QS_CASTLE - 2;
 pub const BQR_CASTLE: usize = BK_QS_CASTLE + 1;
 
+/*************************
+**** Search Constants ****
+*************************/
+
+// Maximum search depth (in plies)
+pub const MAX_DEPTH: usize = 64;
+
+// Aspiration Windows: initial half-width of the window, and the depth from which they are used
+pub const ASPIRATION_WINDOW: i32 = 25;
+pub const ASPIRATION_MIN_DEPTH: usize = 4;
+
+// Null Move Pruning: the depth reduction R is NULL_MOVE_R, plus one deeper than NULL_MOVE_ADAPTIVE_DEPTH
+// (NULL_MOVE_ADAPTIVE_DEPTH_EG once the NPM drops below EG_NPM_LIMIT, as zugzwangs get more likely)
+pub const NULL_MOVE_MIN_DEPTH: usize = 2;
+pub const NULL_MOVE_R: usize = 2;
+pub const NULL_MOVE_ADAPTIVE_DEPTH: usize = 6;
+pub const NULL_MOVE_ADAPTIVE_DEPTH_EG: usize = 8;
+
+// Don't try a null move if there's less than this much NPM on the board (Zugzwang)
+pub const NULL_MOVE_MIN_NPM: i32 = ROOK_VALUE_MG;
+
+// Late Move Reductions: only for quiet moves from move number LMR_MIN_MOVES on, at depth LMR_MIN_DEPTH or more
+// The reduction is LMR_BASE + ln( depth ) * ln( move number ) / LMR_DIVISOR
+pub const LMR_MIN_DEPTH: usize = 3;
+pub const LMR_MIN_MOVES: usize = 3;
+pub const LMR_MAX_MOVES: usize = 64; // Size of the reduction table - later moves are reduced as much as this one
+pub const LMR_BASE: f64 = 0.75;
+pub const LMR_DIVISOR: f64 = 2.25;
+
+// Late Move Pruning: up to LMP_MAX_DEPTH, skip quiet moves after the first LMP_BASE_MOVES + depth^2
+pub const LMP_MAX_DEPTH: usize = 3;
+pub const LMP_BASE_MOVES: usize = 3;
+
+// Move Ordering: killer moves per ply, and the bound on the history scores
+pub const NUM_KILLERS: usize = 2;
+pub const HISTORY_MAX: i32 = 16384;
+
+// Delta Pruning: skip captures that can't raise alpha even with this much to spare
+pub const DELTA_MARGIN: i32 = 200;
+
+// Reverse Futility Pruning (Static Null Move Pruning): up to RFP_MAX_DEPTH, fail high if the static eval beats beta by RFP_MARGIN per ply
+pub const RFP_MAX_DEPTH: usize = 6;
+pub const RFP_MARGIN: i32 = 80;
+
+// Futility Pruning: up to FUTILITY_MAX_DEPTH, skip quiet moves if the static eval plus FUTILITY_MARGIN per ply can't raise alpha
+pub const FUTILITY_MAX_DEPTH: usize = 2;
+pub const FUTILITY_MARGIN: i32 = 175;
+
+// Razoring: up to RAZOR_MAX_DEPTH, drop into quiescence if the static eval is RAZOR_MARGIN per ply below alpha
+pub const RAZOR_MAX_DEPTH: usize = 2;
+pub const RAZOR_MARGIN: i32 = 250;
+
+// Extensions: only while ply < EXTENSION_PLY_FACTOR * the root depth (and ply + depth < MAX_DEPTH), so that they can't explode the search
+pub const EXTENSION_PLY_FACTOR: usize = 2;
+
+// Singular Extensions: from SE_MIN_DEPTH on, if the hash move's entry is at most SE_DEPTH_MARGIN plies shallower,
+// and no other move comes within SE_MARGIN per ply of its value, extend it
+pub const SE_MIN_DEPTH: usize = 8;
+pub const SE_DEPTH_MARGIN: usize = 3;
+pub const SE_MARGIN: i32 = 2;
+
+// Mate Solver: proof and disproof numbers are capped at PN_INF (that's a proven or disproven node)
+pub const PN_INF: u64 = 1 << 40;
+pub const MATE_SOLVER_HASH_BITS: usize = 20;
+pub const MATE_SOLVER_MAX_MOVES: usize = 127; // Depths ( 2 * moves - 1 ) are kept in u8s, below NO_DEPTH
+
 /*****************************
 **** Evaluation Constants ****
 *****************************/
@@ -149,6 +216,7 @@ pub const MG_PHASE: i32 = 128;
 // Game Termination Values
 pub const DRAW_VALUE: i32 = 0;
 pub const MATE_VALUE: i32 = 32000;
+pub const MATE_BOUND: i32 = MATE_VALUE - 1000; // Scores beyond this are mates (MATE_VALUE - plies to mate)
 
 // Tempo Bonus
 // Will depend on your evaluation function of course. The PST Evaluation doesn't account for Tempo at all.
@@ -159,6 +227,71 @@ pub const TEMPO_BONUS: i32 = 16;
 // Bishop Pair Bonus
 pub const BISHOP_PAIR_BONUS: i32 = 50;
 
+// Pawn Structure: mg / eg weights, per pawn unless stated otherwise
+// Rank tables are indexed by the rank relative to the pawn's side (1st rank = 0)
+// FIXME: Guessed values, to be tuned.
+pub const PAWN_HASH_BITS: usize = 14; // The pawn hash table caches these, per pawn_hash
+pub const PASSED_PAWN_MG: [i32; 8] = [0, 5, 10, 15, 25, 40, 60, 0];
+pub const PASSED_PAWN_EG: [i32; 8] = [0, 10, 15, 25, 45, 75, 115, 0];
+pub const SUPPORTED_PASSED_PAWN_PCT: i32 = 125; // Defended by a pawn of its own
+pub const BLOCKED_PASSED_PAWN_PCT: i32 = 50; // Something (anything) stands on the square in front of it
+pub const CONNECTED_PAWN_MG: [i32; 8] = [0, 0, 4, 6, 10, 18, 30, 0];
+pub const CONNECTED_PAWN_EG: [i32; 8] = [0, 0, 2, 4, 8, 14, 24, 0];
+pub const ISOLATED_PAWN_MG: i32 = -10;
+pub const ISOLATED_PAWN_EG: i32 = -15;
+pub const DOUBLED_PAWN_MG: i32 = -10; // For every pawn on the file after the first
+pub const DOUBLED_PAWN_EG: i32 = -20;
+pub const BACKWARD_PAWN_MG: i32 = -8;
+pub const BACKWARD_PAWN_EG: i32 = -10;
+pub const PAWN_ISLAND_MG: i32 = -4; // For every island after the first
+pub const PAWN_ISLAND_EG: i32 = -8;
+
+// Mobility: mg / eg weights per safe square, over ( or under ) the typical number of them - [ Knight, Bishop, Rook, Queen ]
+// FIXME: Guessed values, to be tuned.
+pub const MOBILITY_BASE: [i32; 4] = [4, 6, 7, 13];
+pub const MOBILITY_MG: [i32; 4] = [4, 4, 2, 1];
+pub const MOBILITY_EG: [i32; 4] = [4, 5, 4, 2];
+
+// King Safety: from the point of view of the king's side, scaled down with the game phase (gone by EG_NPM_LIMIT)
+// FIXME: Guessed values, to be tuned.
+pub const KING_SHIELD_PAWN: [i32; 2] = [15, 8]; // Pawn of its own 1 / 2 ranks in front of the king, on its file or an adjacent one
+pub const KING_STORM_PAWN: [i32; 4] = [-5, -20, -12, -6]; // Enemy pawn 1 / 2 / 3 / 4 ranks in front, likewise
+pub const KING_OPEN_FILE: i32 = -25; // No pawns on the king's file or an adjacent one
+pub const KING_SEMI_OPEN_FILE: i32 = -12; // Enemy pawns only
+pub const KING_ATTACKER_WEIGHT: [usize; 4] = [2, 2, 3, 5]; // Per king zone square attacked - [ Knight, Bishop, Rook, Queen ]
+pub const KING_MIN_ATTACKERS: usize = 2; // A lone attacker isn't an attack
+pub const KING_SAFETY_TABLE: [i32; 64] = [
+    0, 0, 0, 1, 1, 2, 3, 4, 6, 7, 9, 11, 13, 15, 17, 19, 22, 25, 28, 31, 34, 37, 41, 42, 44, 48,
+    52, 56, 61, 65, 70, 75, 84, 90, 95, 101, 106, 112, 118, 124, 130, 136, 141, 147, 153, 159, 165,
+    171, 177, 183, 188, 194, 200, 206, 212, 218, 224, 229, 235, 241, 247, 250, 250, 250,
+]; // Penalty by the weighted attacks on the king zone
+
+// Pieces: mg / eg weights. Squares are White's, and mirrored for Black.
+// FIXME: Guessed values, to be tuned.
+pub const KNIGHT_OUTPOST_MG: i32 = 20; // On the 4th to 6th rank, defended by a pawn, and no enemy pawn can ever attack it
+pub const KNIGHT_OUTPOST_EG: i32 = 10;
+pub const BISHOP_OUTPOST_MG: i32 = 10;
+pub const BISHOP_OUTPOST_EG: i32 = 5;
+pub const ROOK_OPEN_FILE_MG: i32 = 25; // No pawns on its file
+pub const ROOK_OPEN_FILE_EG: i32 = 10;
+pub const ROOK_SEMI_OPEN_FILE_MG: i32 = 12; // Enemy pawns only
+pub const ROOK_SEMI_OPEN_FILE_EG: i32 = 6;
+pub const ROOK_ON_SEVENTH_MG: i32 = 20; // If the enemy king is on the 8th rank, or there are enemy pawns on the 7th
+pub const ROOK_ON_SEVENTH_EG: i32 = 30;
+pub const BAD_BISHOP_PAWN_MG: i32 = -3; // Per pawn of its own on its colour - blocked ones count twice
+pub const BAD_BISHOP_PAWN_EG: i32 = -5;
+pub const EARLY_QUEEN_MG: i32 = -8; // Queen off d1, per minor piece still on b1, c1, f1 or g1
+pub const EARLY_QUEEN_EG: i32 = 0;
+pub const TRAPPED_BISHOP_MG: i32 = -80;
+pub const TRAPPED_BISHOP_EG: i32 = -60;
+pub const TRAPPED_ROOK_MG: i32 = -40; // Shut in by a king that has given up castling
+pub const TRAPPED_ROOK_EG: i32 = -10;
+
+// ( Bishop, enemy Pawn ) squares that trap the Bishop: a7 / b6, h7 / g6, a6 / b5, h6 / g5
+pub const TRAPPED_BISHOP_SQUARES: [(usize, usize); 4] = [(48, 41), (55, 46), (40, 33), (47, 38)];
+pub const QUEEN_START: usize = 3; // d1
+pub const MINOR_STARTS: u64 = 0x0000000000000066u64; // b1, c1, f1, g1
+
 // Piece square tables
 // https://chessprogramming.wikispaces.com/Simplified+evaluation+function
 // NOTE: The columns are inverted for both White [ h -> a ] and Black [ a -> h ]. KEEP THE PST SYMMETRIC AROUND THE VERTICAL AXIS!
@@ -1 +1,372 @@
 //! Evaluation
+//! The search is generic over an Evaluator, so that evaluators can be swapped (and compared) without touching the search.
+//! All values are in centi-pawns, from the side to move's point of view.
+
+use crate::consts::*;
+use crate::hashtables::*;
+use crate::pawns::*;
+use crate::state::*;
+use crate::utils::*;
+use std::cmp;
+
+pub trait Evaluator: Clone + Send {
+    // Called at the start of every search, with the root position - to set up any incremental state
+    fn init(&mut self, _state: &State) {}
+
+    // Incremental hooks: called right after the State has made / unmade the move.
+    // Null moves don't change the board, and don't call these.
+    fn make(&mut self, _state: &State, _mv: &Move) {}
+    fn unmake(&mut self, _state: &State, _mv: &Move) {}
+
+    // Evaluation from scratch
+    fn full_eval(&self, state: &State) -> i32;
+
+    // Evaluation of the current position, using whatever was kept up to date by the hooks
+    fn eval(&mut self, state: &State) -> i32 {
+        self.full_eval(state)
+    }
+}
+
+// Tapered Evaluation using Piece-Square-Tables. The State already keeps the PSTEval up to date on make / unmake.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PSTEvaluator;
+
+impl Evaluator for PSTEvaluator {
+    fn full_eval(&self, state: &State) -> i32 {
+        state.compute_pst_eval().eval(state.to_move)
+    }
+
+    #[inline]
+    fn eval(&mut self, state: &State) -> i32 {
+        state.pst_eval()
+    }
+}
+
+// Terms of the HeuristicEvaluator, each of which can be switched off (e.g. to measure what it's worth)
+#[derive(Copy, Clone, Debug)]
+pub struct EvalTerms {
+    pub pawn_structure: bool,
+    pub mobility: bool,
+    pub king_safety: bool,
+    pub pieces: bool, // Outposts, Rooks on (semi-)open files and the 7th, bad and trapped pieces, early Queen
+}
+
+impl Default for EvalTerms {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EvalTerms {
+    pub fn new() -> Self {
+        EvalTerms {
+            pawn_structure: true,
+            mobility: true,
+            king_safety: true,
+            pieces: true,
+        }
+    }
+}
+
+// Tapered Evaluation: the PST's, plus the EvalTerms. Pawn structure is cached in a pawn hash table of its own.
+#[derive(Clone)]
+pub struct HeuristicEvaluator {
+    pub terms: EvalTerms,
+    pub pawn_table: HashTable<PawnEval>,
+}
+
+impl Default for HeuristicEvaluator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeuristicEvaluator {
+    pub fn new() -> Self {
+        HeuristicEvaluator {
+            terms: EvalTerms::new(),
+            pawn_table: HashTable::new(PAWN_HASH_BITS),
+        }
+    }
+
+    // All the terms on top of the PSTEval
+    fn evaluate(&self, state: &State, mut pst_eval: PSTEval, pawns: Option<PawnEval>) -> i32 {
+        if let Some(pawns) = pawns {
+            let (passed_mg, passed_eg) = passed_pawns_eval(state, pawns.passed);
+            pst_eval.eval_mg += pawns.eval_mg + passed_mg;
+            pst_eval.eval_eg += pawns.eval_eg + passed_eg;
+        }
+
+        if self.terms.mobility {
+            let (mobility_mg, mobility_eg) = mobility_eval(state);
+            pst_eval.eval_mg += mobility_mg;
+            pst_eval.eval_eg += mobility_eg;
+        }
+
+        if self.terms.pieces {
+            let (pieces_mg, pieces_eg) = piece_eval(state);
+            pst_eval.eval_mg += pieces_mg;
+            pst_eval.eval_eg += pieces_eg;
+        }
+
+        if self.terms.king_safety {
+            let king_safety = king_safety_eval(state) * pst_eval.phase() / MG_PHASE;
+            pst_eval.eval_mg += king_safety;
+            pst_eval.eval_eg += king_safety;
+        }
+
+        pst_eval.eval(state.to_move)
+    }
+}
+
+impl Evaluator for HeuristicEvaluator {
+    fn full_eval(&self, state: &State) -> i32 {
+        let pawns = self.terms.pawn_structure.then(|| pawn_eval(state));
+        self.evaluate(state, state.compute_pst_eval(), pawns)
+    }
+
+    fn eval(&mut self, state: &State) -> i32 {
+        let pawns = self.terms.pawn_structure.then(|| {
+            self.pawn_table.get(state.pawn_hash, 0).unwrap_or_else(|| {
+                let pawns = pawn_eval(state);
+                self.pawn_table.set(state.pawn_hash, 0, pawns);
+                pawns
+            })
+        });
+
+        self.evaluate(state, state.pst_eval, pawns)
+    }
+}
+
+// Squares attacked by the piece on pos, off the control maps. The enemy sliders' control goes through the king of
+// the side to move (so that it can't step back along a check) - that x-ray is left out, to see both sides alike.
+#[inline]
+pub fn piece_control(state: &State, pos: usize) -> u64 {
+    let control = state.control[pos];
+    let king = state.bit_board[state.to_move | KING];
+    if control & king == 0 {
+        return control;
+    }
+
+    let king_pos = king.trailing_zeros() as usize;
+    let beyond_king = if king_pos > pos {
+        (u64::MAX << king_pos) << 1
+    } else {
+        (1 << king_pos) - 1
+    };
+    control & !(line(pos, king_pos) & beyond_king)
+}
+
+// ( mg, eg ) of the Knights', Bishops', Rooks' and Queens' mobility, from White's point of view.
+// Counts the squares a piece attacks that aren't taken by its own side or attacked by enemy pawns - along its pin, if pinned.
+pub fn mobility_eval(state: &State) -> (i32, i32) {
+    let mut eval_mg = 0;
+    let mut eval_eg = 0;
+
+    for color in [WHITE, BLACK] {
+        let opp_color = color ^ COLOR;
+        let safe = !state.bit_board[color | ALL]
+            & !pawn_attacks(state.bit_board[opp_color | PAWN], opp_color);
+        let sign = if color == WHITE { 1 } else { -1 };
+
+        for piece_type in [KNIGHT, BISHOP, ROOK, QUEEN] {
+            let index = (piece_type >> 1) as usize - 1;
+            let mut bb = state.bit_board[color | piece_type];
+            while bb != 0 {
+                let pos = pop_lsb_pos(&mut bb);
+                let squares =
+                    (piece_control(state, pos) & safe & state.a_pins[pos]).count_ones() as i32;
+                eval_mg += sign * (squares - MOBILITY_BASE[index]) * MOBILITY_MG[index];
+                eval_eg += sign * (squares - MOBILITY_BASE[index]) * MOBILITY_EG[index];
+            }
+        }
+    }
+
+    (eval_mg, eval_eg)
+}
+
+// King Safety from White's point of view - not yet scaled with the game phase
+pub fn king_safety_eval(state: &State) -> i32 {
+    side_king_safety(state, WHITE) - side_king_safety(state, BLACK)
+}
+
+// How safe color's king is: the pawns in front of it - its own, and enemy ones storming it - and the attacks on the squares around it
+fn side_king_safety(state: &State, color: u8) -> i32 {
+    let opp_color = color ^ COLOR;
+    let king_pos = state.bit_board[color | KING].trailing_zeros() as usize;
+    let (king_file, king_rank) = file_rank(king_pos);
+    let own_pawns = state.bit_board[color | PAWN];
+    let opp_pawns = state.bit_board[opp_color | PAWN];
+
+    let mut safety = 0;
+
+    // Pawn shield, pawn storm and open files - on the king's file and the adjacent ones
+    for file in king_file.saturating_sub(1)..=cmp::min(king_file + 1, 7) {
+        let file_bb = A_FILE << file;
+        let front = front_span(1 << (8 * king_rank + file), color);
+
+        // Ranks from the king to the closest pawn in front of it, on this file
+        let distance = |pawns: u64| {
+            let pawns = pawns & front;
+            if pawns == 0 {
+                None
+            } else {
+                let pos = if color == WHITE {
+                    pawns.trailing_zeros()
+                } else {
+                    63 - pawns.leading_zeros()
+                };
+                Some((pos as usize / 8).abs_diff(king_rank))
+            }
+        };
+
+        if let Some(rank) = distance(own_pawns)
+            && rank <= KING_SHIELD_PAWN.len()
+        {
+            safety += KING_SHIELD_PAWN[rank - 1];
+        }
+        if let Some(rank) = distance(opp_pawns)
+            && rank <= KING_STORM_PAWN.len()
+        {
+            safety += KING_STORM_PAWN[rank - 1];
+        }
+
+        if own_pawns & file_bb == 0 {
+            safety += if opp_pawns & file_bb == 0 {
+                KING_OPEN_FILE
+            } else {
+                KING_SEMI_OPEN_FILE
+            };
+        }
+    }
+
+    // Attacks on the king zone: the squares around the king, and the ones in front of those
+    let ring = state.mg.k_captures(king_pos) | 1 << king_pos;
+    let zone = ring | forward(ring, color);
+    let mut num_attackers = 0;
+    let mut attack_units = 0;
+
+    for piece_type in [KNIGHT, BISHOP, ROOK, QUEEN] {
+        let index = (piece_type >> 1) as usize - 1;
+        let mut bb = state.bit_board[opp_color | piece_type];
+        while bb != 0 {
+            let pos = pop_lsb_pos(&mut bb);
+            let attacks = piece_control(state, pos) & zone;
+            if attacks != 0 {
+                num_attackers += 1;
+                attack_units += KING_ATTACKER_WEIGHT[index] * attacks.count_ones() as usize;
+            }
+        }
+    }
+
+    if num_attackers >= KING_MIN_ATTACKERS {
+        safety -= KING_SAFETY_TABLE[cmp::min(attack_units, KING_SAFETY_TABLE.len() - 1)];
+    }
+
+    safety
+}
+
+// ( mg, eg ) of the piece-specific terms, from White's point of view
+pub fn piece_eval(state: &State) -> (i32, i32) {
+    let (white_mg, white_eg) = side_piece_eval(state, WHITE);
+    let (black_mg, black_eg) = side_piece_eval(state, BLACK);
+
+    (white_mg - black_mg, white_eg - black_eg)
+}
+
+// ( mg, eg ) of color's pieces
+fn side_piece_eval(state: &State, color: u8) -> (i32, i32) {
+    let opp_color = color ^ COLOR;
+    let own_pawns = state.bit_board[color | PAWN];
+    let opp_pawns = state.bit_board[opp_color | PAWN];
+    let occupancy = state.bit_board[WHITE_ALL] | state.bit_board[BLACK_ALL];
+
+    // White's squares, ranks and masks, as color's
+    let relative = |pos: usize| if color == WHITE { pos } else { pos ^ 56 };
+    let relative_bb = |bb: u64| if color == WHITE { bb } else { bb.swap_bytes() };
+    let rank_bb = |rank: usize| relative_bb(FIRST_RANK << (8 * rank));
+
+    let mut eval_mg = 0;
+    let mut eval_eg = 0;
+
+    // Outposts
+    let opp_reach = forward_fill(pawn_attacks(opp_pawns, opp_color), opp_color);
+    let outposts =
+        (rank_bb(3) | rank_bb(4) | rank_bb(5)) & pawn_attacks(own_pawns, color) & !opp_reach;
+    let knight_outposts = (state.bit_board[color | KNIGHT] & outposts).count_ones() as i32;
+    let bishop_outposts = (state.bit_board[color | BISHOP] & outposts).count_ones() as i32;
+    eval_mg += knight_outposts * KNIGHT_OUTPOST_MG + bishop_outposts * BISHOP_OUTPOST_MG;
+    eval_eg += knight_outposts * KNIGHT_OUTPOST_EG + bishop_outposts * BISHOP_OUTPOST_EG;
+
+    // Rooks on open and semi-open files, and on the 7th
+    let seventh = rank_bb(6);
+    let on_seventh =
+        state.bit_board[opp_color | KING] & rank_bb(7) != 0 || opp_pawns & seventh != 0;
+    let mut bb = state.bit_board[color | ROOK];
+    while bb != 0 {
+        let pos = pop_lsb_pos(&mut bb);
+        let file_bb = A_FILE << (pos % 8);
+        if own_pawns & file_bb == 0 {
+            if opp_pawns & file_bb == 0 {
+                eval_mg += ROOK_OPEN_FILE_MG;
+                eval_eg += ROOK_OPEN_FILE_EG;
+            } else {
+                eval_mg += ROOK_SEMI_OPEN_FILE_MG;
+                eval_eg += ROOK_SEMI_OPEN_FILE_EG;
+            }
+        }
+        if on_seventh && seventh & (1 << pos) != 0 {
+            eval_mg += ROOK_ON_SEVENTH_MG;
+            eval_eg += ROOK_ON_SEVENTH_EG;
+        }
+    }
+
+    // Bad Bishops: hemmed in by pawns of their own on their colour - all the more if those can't move
+    let blocked_pawns = own_pawns & forward(occupancy, opp_color);
+    let mut bb = state.bit_board[color | BISHOP];
+    while bb != 0 {
+        let pos = pop_lsb_pos(&mut bb);
+        let squares = if ALL_WHITE_SQUARES & (1 << pos) != 0 {
+            ALL_WHITE_SQUARES
+        } else {
+            ALL_BLACK_SQUARES
+        };
+        let pawns = (own_pawns & squares).count_ones() as i32
+            + (blocked_pawns & squares).count_ones() as i32;
+        eval_mg += pawns * BAD_BISHOP_PAWN_MG;
+        eval_eg += pawns * BAD_BISHOP_PAWN_EG;
+    }
+
+    // Queen out before the minor pieces
+    let queens = state.bit_board[color | QUEEN];
+    if queens != 0 && queens & (1 << relative(QUEEN_START)) == 0 {
+        let minors = state.bit_board[color | KNIGHT] | state.bit_board[color | BISHOP];
+        let undeveloped = (minors & relative_bb(MINOR_STARTS)).count_ones() as i32;
+        eval_mg += undeveloped * EARLY_QUEEN_MG;
+        eval_eg += undeveloped * EARLY_QUEEN_EG;
+    }
+
+    // Bishops trapped by enemy pawns (say, after grabbing a pawn on a7)
+    for (bishop_pos, pawn_pos) in TRAPPED_BISHOP_SQUARES {
+        if state.simple_board[relative(bishop_pos)] == color | BISHOP
+            && state.simple_board[relative(pawn_pos)] == opp_color | PAWN
+        {
+            eval_mg += TRAPPED_BISHOP_MG;
+            eval_eg += TRAPPED_BISHOP_EG;
+        }
+    }
+
+    // Rooks shut in the corner by their own king (which can no longer castle): Kf1 / Kg1 with Rg1 / Rh1 / Rh2, and Kb1 / Kc1 likewise
+    let king_pos = relative(state.bit_board[color | KING].trailing_zeros() as usize);
+    let corner = match king_pos {
+        5 | 6 => (FIRST_RANK & !((2 << king_pos) - 1)) | 1 << 15,
+        1 | 2 => ((1 << king_pos) - 1) | 1 << 8,
+        _ => 0,
+    };
+    if state.bit_board[color | ROOK] & relative_bb(corner) != 0 {
+        eval_mg += TRAPPED_ROOK_MG;
+        eval_eg += TRAPPED_ROOK_EG;
+    }
+
+    (eval_mg, eval_eg)
+}
@@ -1,7 +1,20 @@
 //! Implement required hash tables here
 
-use crate::consts::*;
+use std::array;
+use std::cmp;
 use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+
+// Number of HashItems per bucket
+pub const BUCKET_SIZE: usize = 4;
+
+// Depth of an empty HashItem
+pub const NO_DEPTH: u8 = !0u8;
+
+// An entry this many generations old is worth one ply less when choosing whom to replace
+pub const AGE_WEIGHT: i32 = 4;
 
 // Shorthand to represent the combination of a few traits.
 pub trait HashReq: Copy + Clone + Debug + Default {}
@@ -10,46 +23,74 @@ impl<T: Copy + Clone + Debug + Default> HashReq for T {}
 #[derive(Copy, Clone, Debug)]
 pub struct HashItem<T: HashReq> {
     pub hash: u64,
-    pub depth: usize,
     pub value: T,
+    pub depth: u8,
+    pub age: u8, // Generation of the search that last stored this
 }
 
-// This trait provides an interface to decide when to update the HashItem.
+// This trait provides an interface to decide when to update the HashItem (holding the same hash).
 pub trait UpdateHash<T: HashReq> {
-    fn update(&self, depth: usize, value: T) -> bool;
+    fn update(&self, hash: u64, depth: usize, value: T) -> bool;
 }
 
 impl<T: HashReq> UpdateHash<T> for HashItem<T> {
     #[inline]
-    default fn update(&self, _depth: usize, _value: T) -> bool {
+    default fn update(&self, _hash: u64, _depth: usize, _value: T) -> bool {
         true // Always update
     }
 }
 
 impl UpdateHash<u64> for HashItem<u64> {
     #[inline]
-    fn update(&self, _depth: usize, value: u64) -> bool {
-        self.depth == ERR_POS || self.value < value
+    fn update(&self, _hash: u64, _depth: usize, value: u64) -> bool {
+        self.is_empty() || self.value < value
     }
 }
 
 impl<T: HashReq> HashItem<T> {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.depth == NO_DEPTH
+    }
+
     #[inline]
     pub fn get(&self, hash: u64, depth: usize) -> Option<T> {
-        if self.depth == depth && self.hash == hash {
+        if !self.is_empty() && self.depth as usize == depth && self.hash == hash {
             Some(self.value)
         } else {
             None
         }
     }
 
+    // Ignores depth, returns ( depth, value )
     #[inline]
-    pub fn set(&mut self, hash: u64, depth: usize, new_value: T) {
-        if self.update(depth, new_value) {
+    pub fn probe(&self, hash: u64) -> Option<(usize, T)> {
+        if !self.is_empty() && self.hash == hash {
+            Some((self.depth as usize, self.value))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub fn set(&mut self, hash: u64, depth: usize, new_value: T, generation: u8) {
+        if self.is_empty() || self.hash != hash || self.update(hash, depth, new_value) {
             self.hash = hash;
-            self.depth = depth;
+            self.depth = depth as u8;
             self.value = new_value;
         }
+
+        self.age = generation;
+    }
+
+    // How much we'd like to keep this - deep and recent entries are worth more
+    #[inline]
+    pub fn worth(&self, generation: u8) -> i32 {
+        if self.is_empty() {
+            i32::MIN
+        } else {
+            self.depth as i32 - AGE_WEIGHT * generation.wrapping_sub(self.age) as i32
+        }
     }
 }
 
@@ -57,32 +98,248 @@ impl<T: HashReq> Default for HashItem<T> {
     fn default() -> Self {
         HashItem {
             hash: 0,
-            depth: ERR_POS,
             value: Default::default(),
+            depth: NO_DEPTH,
+            age: 0,
         }
     }
 }
 
+// Buckets are aligned to cache lines
+#[repr(align(64))]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Bucket<T: HashReq>(pub [HashItem<T>; BUCKET_SIZE]);
+
+#[derive(Clone)]
 pub struct HashTable<T: HashReq> {
     pub index_mask: usize,
-    pub table: Vec<HashItem<T>>,
+    pub table: Vec<Bucket<T>>,
+    pub generation: u8, // Bumped per search, used to age out entries from earlier searches
 }
 
 impl<T: HashReq> HashTable<T> {
+    // A table with (at least BUCKET_SIZE and) 2^num_bits entries
     pub fn new(num_bits: usize) -> Self {
-        let size: usize = 1 << num_bits;
+        Self::with_buckets((1 << num_bits) / BUCKET_SIZE)
+    }
+
+    // The largest table that fits in the given size (in MB)
+    pub fn with_mb(mb: usize) -> Self {
+        Self::with_buckets((mb << 20) / mem::size_of::<Bucket<T>>())
+    }
+
+    // Rounds down to a power of 2
+    pub fn with_buckets(num_buckets: usize) -> Self {
+        let size: usize = 1 << (usize::BITS - 1 - cmp::max(num_buckets, 1).leading_zeros());
 
         HashTable {
             index_mask: size - 1,
             table: vec![Default::default(); size],
+            generation: 0,
         }
     }
 
+    pub fn resize(&mut self, mb: usize) {
+        *self = Self::with_mb(mb);
+    }
+
+    pub fn clear(&mut self) {
+        self.table.fill(Default::default());
+        self.generation = 0;
+    }
+
+    // To be called at the start of every search
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    // Permille of (a sample of) the entries that were stored in the current search
+    pub fn hashfull(&self) -> usize {
+        let num_buckets = cmp::min(self.table.len(), 1000 / BUCKET_SIZE);
+        let used = self.table[..num_buckets]
+            .iter()
+            .flat_map(|bucket| bucket.0.iter())
+            .filter(|item| !item.is_empty() && item.age == self.generation)
+            .count();
+
+        used * 1000 / (num_buckets * BUCKET_SIZE)
+    }
+
+    #[inline]
+    pub fn bucket(&self, hash: u64) -> &Bucket<T> {
+        &self.table[hash as usize & self.index_mask]
+    }
+
     pub fn get(&self, hash: u64, depth: usize) -> Option<T> {
-        self.table[hash as usize & self.index_mask].get(hash, depth)
+        self.bucket(hash)
+            .0
+            .iter()
+            .find_map(|item| item.get(hash, depth))
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<(usize, T)> {
+        self.bucket(hash).0.iter().find_map(|item| item.probe(hash))
     }
 
+    // Update the item holding the same hash, if any - else replace the item worth the least
     pub fn set(&mut self, hash: u64, depth: usize, new_value: T) {
-        self.table[hash as usize & self.index_mask].set(hash, depth, new_value)
+        let generation = self.generation;
+        let bucket = &mut self.table[hash as usize & self.index_mask].0;
+
+        let index = match bucket
+            .iter()
+            .position(|item| !item.is_empty() && item.hash == hash)
+        {
+            Some(index) => index,
+            None => (0..BUCKET_SIZE)
+                .min_by_key(|&i| bucket[i].worth(generation))
+                .unwrap(),
+        };
+
+        bucket[index].set(hash, depth, new_value, generation);
+    }
+}
+
+// Values that can be packed into 48 bits, to be stored in a SharedHashTable
+pub trait Pack: HashReq {
+    fn pack(&self) -> u64;
+    fn unpack(data: u64) -> Self;
+}
+
+// Lock-free HashItem: ( hash ^ data, data ), where data packs ( value, depth, age ).
+// Threads may race to write the same item - a torn item (key and data from different writes) fails the hash check,
+// and is treated as an entry for some other position.
+#[derive(Debug, Default)]
+pub struct SharedItem {
+    pub key: AtomicU64,
+    pub data: AtomicU64,
+}
+
+impl SharedItem {
+    #[inline]
+    pub fn load<T: Pack>(&self) -> HashItem<T> {
+        let data = self.data.load(Ordering::Relaxed);
+        let key = self.key.load(Ordering::Relaxed);
+
+        if data == 0 {
+            Default::default()
+        } else {
+            HashItem {
+                hash: key ^ data,
+                value: T::unpack(data >> 16),
+                depth: ((data >> 8) as u8).wrapping_sub(1), // Stored off by one, so that only empty items are all zeros
+                age: data as u8,
+            }
+        }
+    }
+
+    #[inline]
+    pub fn store<T: Pack>(&self, item: &HashItem<T>) {
+        let data =
+            item.value.pack() << 16 | (item.depth.wrapping_add(1) as u64) << 8 | item.age as u64;
+        self.key.store(item.hash ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+}
+
+#[repr(align(64))]
+#[derive(Debug, Default)]
+pub struct SharedBucket(pub [SharedItem; BUCKET_SIZE]);
+
+// A HashTable that can be shared between threads (Lazy SMP): same interface and replacement scheme, but takes &self
+pub struct SharedHashTable<T: Pack> {
+    pub index_mask: usize,
+    pub table: Vec<SharedBucket>,
+    pub generation: AtomicU8,
+    pub phantom: PhantomData<T>,
+}
+
+impl<T: Pack> SharedHashTable<T> {
+    pub fn new(num_bits: usize) -> Self {
+        Self::with_buckets((1 << num_bits) / BUCKET_SIZE)
+    }
+
+    pub fn with_mb(mb: usize) -> Self {
+        Self::with_buckets((mb << 20) / mem::size_of::<SharedBucket>())
+    }
+
+    pub fn with_buckets(num_buckets: usize) -> Self {
+        let size: usize = 1 << (usize::BITS - 1 - cmp::max(num_buckets, 1).leading_zeros());
+
+        SharedHashTable {
+            index_mask: size - 1,
+            table: (0..size).map(|_| Default::default()).collect(),
+            generation: AtomicU8::new(0),
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn resize(&mut self, mb: usize) {
+        *self = Self::with_mb(mb);
+    }
+
+    pub fn clear(&self) {
+        for item in self.table.iter().flat_map(|bucket| bucket.0.iter()) {
+            item.key.store(0, Ordering::Relaxed);
+            item.data.store(0, Ordering::Relaxed);
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn hashfull(&self) -> usize {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let num_buckets = cmp::min(self.table.len(), 1000 / BUCKET_SIZE);
+        let used = self.table[..num_buckets]
+            .iter()
+            .flat_map(|bucket| bucket.0.iter())
+            .map(|item| item.load::<T>())
+            .filter(|item| !item.is_empty() && item.age == generation)
+            .count();
+
+        used * 1000 / (num_buckets * BUCKET_SIZE)
+    }
+
+    #[inline]
+    pub fn bucket(&self, hash: u64) -> &SharedBucket {
+        &self.table[hash as usize & self.index_mask]
+    }
+
+    pub fn get(&self, hash: u64, depth: usize) -> Option<T> {
+        self.bucket(hash)
+            .0
+            .iter()
+            .find_map(|item| item.load::<T>().get(hash, depth))
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<(usize, T)> {
+        self.bucket(hash)
+            .0
+            .iter()
+            .find_map(|item| item.load::<T>().probe(hash))
+    }
+
+    // Same as HashTable::set, on a snapshot of the bucket
+    pub fn set(&self, hash: u64, depth: usize, new_value: T) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let bucket = &self.bucket(hash).0;
+        let items: [HashItem<T>; BUCKET_SIZE] = array::from_fn(|i| bucket[i].load());
+
+        let index = match items
+            .iter()
+            .position(|item| !item.is_empty() && item.hash == hash)
+        {
+            Some(index) => index,
+            None => (0..BUCKET_SIZE)
+                .min_by_key(|&i| items[i].worth(generation))
+                .unwrap(),
+        };
+
+        let mut item = items[index];
+        item.set(hash, depth, new_value, generation);
+        bucket[index].store(&item);
     }
 }
@@ -2,7 +2,7 @@
 
 use crate::consts::*;
 use crate::utils::*;
-use rand::{rng, Rng};
+use rand::{Rng, rng};
 
 // shift = 64 - hash_num_bits, where hash_num_bits = number of bits set in the mask
 pub const ROOK_SHIFTS: [u8; 64] = [
@@ -178,11 +178,14 @@ pub fn hashed_attacks(
     Some(hashed_attacks)
 }
 
+// Attack function for sliding pieces
+pub type AttackFn = fn(usize, u64) -> u64;
+
 // Returns magic number and hashed_attacks
 pub fn magic(pos: usize, piece: u8, stored: bool, verbose: bool) -> (u64, Vec<u64>) {
     assert!(pos < 64, "Square address out of bounds!");
 
-    let (mask, shift, attack, stored_magic): (u64, u8, fn(usize, u64) -> u64, u64) = match piece {
+    let (mask, shift, attack, stored_magic): (u64, u8, AttackFn, u64) = match piece {
         ROOK => (
             rook_mask(pos),
             ROOK_SHIFTS[pos],
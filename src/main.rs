@@ -9,33 +9,116 @@ pub mod evaluation;
 pub mod hash;
 pub mod hashtables;
 pub mod magics;
+pub mod mate_solver;
 pub mod movegen;
+pub mod movepick;
+pub mod pawns;
 pub mod pgn_parser;
 pub mod search;
 pub mod simple_game;
 pub mod state;
 pub mod testing;
+pub mod timeman;
+pub mod uci;
 pub mod utils;
+pub mod xboard;
 
 use consts::*;
 use hashtables::*;
+use mate_solver::*;
 use search::*;
 use state::*;
+use std::env;
+use std::io;
 use std::time::Instant;
 
 fn main() {
+    match env::args().nth(1).as_deref() {
+        Some("bench") => bench(),
+        Some("play") => simple_game::play(),
+        Some("mate") => mate(&env::args().skip(2).collect::<Vec<String>>()),
+        _ => protocol(),
+    }
+}
+
+// The first command from the GUI decides the protocol: "xboard" for CECP, UCI otherwise
+fn protocol() {
+    let mut first_command = String::new();
+    if let Err(error) = io::stdin().read_line(&mut first_command) {
+        panic!("Error: {}", error);
+    }
+
+    match first_command.trim() {
+        "xboard" => xboard::run(),
+        command => uci::run(command),
+    }
+}
+
+// mate <max_moves> <fen>: max_moves, and the position to solve
+fn parse_mate_args(args: &[String]) -> Result<(usize, State), String> {
+    let max_moves = match args.first().map(|x| x.parse::<usize>()) {
+        Some(Ok(max_moves)) if (1..=MATE_SOLVER_MAX_MOVES).contains(&max_moves) => max_moves,
+        Some(_) => {
+            return Err(format!(
+                "max_moves has to be between 1 and {}",
+                MATE_SOLVER_MAX_MOVES
+            ));
+        }
+        None => return Err("No max_moves given".to_string()),
+    };
+
+    if args.len() < 2 {
+        return Err("No FEN given".to_string());
+    }
+    let state = State::try_from_fen(&args[1..].join(" "))
+        .map_err(|error| format!("Invalid FEN: {}", error))?;
+
+    Ok((max_moves, state))
+}
+
+// mate <max_moves> <fen>: Solve for a mate in at most max_moves moves
+fn mate(args: &[String]) {
+    let (max_moves, mut state) = match parse_mate_args(args) {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            println!("{}", error);
+            println!("Usage: eroica mate <max_moves> <fen>");
+            return;
+        }
+    };
+
+    let start = Instant::now();
+    let mut tt: HashTable<ProofNumbers> = HashTable::new(MATE_SOLVER_HASH_BITS);
+    let mut solver = MateSolver::new(&mut tt);
+    let result = solver.solve(&mut state, max_moves);
+
+    match &result {
+        MateResult::Mate(line) => {
+            let line: Vec<String> = line.iter().map(|mv| mv.long_algebraic()).collect();
+            println!("Mate in {}: {}", result.mate_in().unwrap(), line.join(" "));
+        }
+        MateResult::NoMate => println!("No mate in {} moves", max_moves),
+        MateResult::Unknown => println!("Unknown"),
+    }
+    println!(
+        "Nodes: {}, Time taken: {} seconds",
+        solver.nodes,
+        (start.elapsed().as_nanos() as f32) / 1e9
+    );
+}
+
+fn bench() {
     //testing::perftsuite_bench();
-    // NOTE: Test Commit!
 
     let start = Instant::now();
 
     let mut state = State::new();
-    let mut stats = SearchStats::new();
-    let mut tt: HashTable<Eval> = HashTable::new(24);
+    let mut ctx = SearchContext::new();
+    let tt: SharedHashTable<Eval> = SharedHashTable::new(24);
     //println!( "{}\n", state );
-    let pv = negamax(&mut state, 8, -INF_VALUE, INF_VALUE, &mut stats, &mut tt);
+    let pv = negamax(&mut state, 8, 0, -INF_VALUE, INF_VALUE, &mut ctx, &tt);
     println!("Eval: {}\n", pv.eval);
-    //println!( "{:?}\n", stats );
+    //println!( "{:?}\n", ctx.stats );
     //println!( "{:?}\n", pv.move_list );
 
     println!(
@@ -43,8 +126,6 @@ fn main() {
         (start.elapsed().as_nanos() as f32) / 1e9
     );
 
-    //simple_game::play();
-
     //let fen = "1rbq1rk1/p1b1nppp/1p2p3/8/1B1pN3/P2B4/1P3PPP/2RQ1R1K w - - 0 0";
     //let mut state = State::generate_state_from_fen( fen );
 
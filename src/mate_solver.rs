@@ -0,0 +1,306 @@
+//! Mate Solver - Depth-First Proof-Number Search (df-pn)
+//! Proves (or refutes) a forced mate in at most N moves for the side to move - the attacker.
+//! The attacker's nodes are OR nodes (one mating move will do), the defender's are AND nodes (every defence has to be mated).
+//! The proof number of a node is the (estimated) number of leaves that still have to be proven to prove it, the disproof number likewise.
+//! The search always expands the most-proving node, and stays in a subtree till its numbers exceed the thresholds it was given.
+
+use crate::consts::*;
+use crate::hashtables::*;
+use crate::state::*;
+use std::cmp;
+
+// Proof and disproof numbers of a node. Proven nodes also know how many plies it takes to mate.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProofNumbers {
+    pub pn: u64,
+    pub dn: u64,
+    pub distance: usize,
+}
+
+impl ProofNumbers {
+    pub fn new(pn: u64, dn: u64) -> Self {
+        ProofNumbers {
+            pn,
+            dn,
+            distance: 0,
+        }
+    }
+
+    pub fn proven(distance: usize) -> Self {
+        ProofNumbers {
+            pn: 0,
+            dn: PN_INF,
+            distance,
+        }
+    }
+
+    pub fn disproven() -> Self {
+        ProofNumbers::new(PN_INF, 0)
+    }
+
+    #[inline]
+    pub fn is_proven(&self) -> bool {
+        self.pn == 0
+    }
+
+    #[inline]
+    pub fn is_solved(&self) -> bool {
+        self.pn == 0 || self.dn == 0
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MateResult {
+    Mate(Vec<Move>), // The mating line (with the longest defence)
+    NoMate,          // Disproven: there's no mate in at most the given number of moves
+    Unknown,         // Ran out of nodes
+}
+
+impl MateResult {
+    // Mate in N moves
+    pub fn mate_in(&self) -> Option<usize> {
+        match self {
+            MateResult::Mate(line) => Some(line.len().div_ceil(2)),
+            _ => None,
+        }
+    }
+}
+
+pub struct MateSolver<'a> {
+    pub tt: &'a mut HashTable<ProofNumbers>,
+    pub nodes: u64,
+    pub max_nodes: Option<u64>,
+    pub attacker: u8,
+}
+
+// Entries are per (position, plies left) - a proof with more plies to spare isn't one with fewer
+#[inline]
+fn tt_key(hash: u64, depth: usize) -> u64 {
+    hash ^ (depth as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+impl<'a> MateSolver<'a> {
+    pub fn new(tt: &'a mut HashTable<ProofNumbers>) -> Self {
+        MateSolver {
+            tt,
+            nodes: 0,
+            max_nodes: None,
+            attacker: WHITE,
+        }
+    }
+
+    #[inline]
+    fn out_of_nodes(&self) -> bool {
+        self.max_nodes
+            .is_some_and(|max_nodes| self.nodes >= max_nodes)
+    }
+
+    // Looks for the shortest mate in at most max_moves moves, for the side to move
+    pub fn solve(&mut self, state: &mut State, max_moves: usize) -> MateResult {
+        self.attacker = state.to_move;
+        self.tt.new_search();
+
+        for moves in 1..=max_moves {
+            let depth = 2 * moves - 1;
+            let mut root = self.init_numbers(state, depth, 0);
+            if !root.is_solved() {
+                root = self.mid(state, depth, 0, PN_INF, PN_INF);
+            }
+
+            if root.is_proven() {
+                return match self.mating_line(state, depth, 0) {
+                    Some(line) => MateResult::Mate(line),
+                    None => MateResult::Unknown,
+                };
+            } else if !root.is_solved() {
+                return MateResult::Unknown;
+            }
+        }
+
+        MateResult::NoMate
+    }
+
+    // Numbers to start a node with: terminal nodes and the horizon are solved, then there's what's in the table,
+    // else an estimate - each of the defender's moves needs a proof, any of the attacker's moves a disproof.
+    // Repetitions on the path are disproven - but that depends on the path, which the table doesn't know about (GHI).
+    fn init_numbers(&self, state: &State, depth: usize, ply: usize) -> ProofNumbers {
+        let (legal_moves, status) = state.node_info();
+        let or_node = state.to_move == self.attacker;
+
+        if status == Status::Checkmate {
+            return if or_node {
+                ProofNumbers::disproven()
+            } else {
+                ProofNumbers::proven(0)
+            };
+        }
+
+        if status != Status::Ongoing || depth == 0 || state.is_search_repetition(ply, ply) {
+            return ProofNumbers::disproven();
+        }
+
+        if let Some(numbers) = self.tt.get(tt_key(state.hash, depth), depth) {
+            return numbers;
+        }
+
+        let num_moves = legal_moves.len() as u64;
+        if or_node {
+            ProofNumbers::new(1, num_moves)
+        } else {
+            ProofNumbers::new(num_moves, 1)
+        }
+    }
+
+    // Multiple Iterative Deepening: expand the node till its numbers reach either of the thresholds
+    fn mid(
+        &mut self,
+        state: &mut State,
+        depth: usize,
+        ply: usize,
+        th_pn: u64,
+        th_dn: u64,
+    ) -> ProofNumbers {
+        self.nodes += 1;
+        let or_node = state.to_move == self.attacker;
+        let (legal_moves, _) = state.node_info();
+        let irs = state.ir_state();
+
+        let mut children: Vec<(Move, ProofNumbers)> = Vec::with_capacity(legal_moves.len());
+        for mv in legal_moves {
+            state.make(&mv);
+            let numbers = self.init_numbers(state, depth - 1, ply + 1);
+            state.unmake(&mv, &irs);
+            children.push((mv, numbers));
+        }
+
+        loop {
+            let numbers = node_numbers(&children, or_node);
+            if numbers.pn >= th_pn || numbers.dn >= th_dn || self.out_of_nodes() {
+                self.tt.set(tt_key(state.hash, depth), depth, numbers);
+                return numbers;
+            }
+
+            // The most-proving child: the smallest pn at OR nodes, the smallest dn at AND nodes.
+            // It gets to stay till it's no longer the most-proving one, i.e. till it overtakes the runner-up.
+            let key = |numbers: &ProofNumbers| if or_node { numbers.pn } else { numbers.dn };
+            let mut best = 0;
+            let mut second = PN_INF;
+            for (index, (_, child)) in children.iter().enumerate().skip(1) {
+                if key(child) < key(&children[best].1) {
+                    second = key(&children[best].1);
+                    best = index;
+                } else {
+                    second = cmp::min(second, key(child));
+                }
+            }
+
+            let child = children[best].1;
+            let (child_th_pn, child_th_dn) = if or_node {
+                (
+                    cmp::min(th_pn, second.saturating_add(1)),
+                    cmp::min(th_dn - numbers.dn + child.dn, PN_INF),
+                )
+            } else {
+                (
+                    cmp::min(th_pn - numbers.pn + child.pn, PN_INF),
+                    cmp::min(th_dn, second.saturating_add(1)),
+                )
+            };
+
+            let mv = children[best].0;
+            state.make(&mv);
+            children[best].1 = self.mid(state, depth - 1, ply + 1, child_th_pn, child_th_dn);
+            state.unmake(&mv, &irs);
+        }
+    }
+
+    // Walks down a proven node: the fastest mate for the attacker, the longest defence for the defender.
+    // Anything that dropped out of the table is proven again.
+    fn mating_line(&mut self, state: &mut State, depth: usize, ply: usize) -> Option<Vec<Move>> {
+        let (legal_moves, status) = state.node_info();
+        if status == Status::Checkmate {
+            return Some(Vec::new());
+        }
+
+        let or_node = state.to_move == self.attacker;
+        let irs = state.ir_state();
+        let mut best: Option<(Move, usize)> = None;
+
+        for mv in legal_moves {
+            state.make(&mv);
+            let mut numbers = self.init_numbers(state, depth - 1, ply + 1);
+            if !numbers.is_solved() {
+                numbers = self.mid(state, depth - 1, ply + 1, PN_INF, PN_INF);
+            }
+            state.unmake(&mv, &irs);
+
+            if numbers.is_proven() {
+                let better = match best {
+                    Some((_, distance)) if or_node => numbers.distance < distance,
+                    Some((_, distance)) => numbers.distance > distance,
+                    None => true,
+                };
+                if better {
+                    best = Some((mv, numbers.distance));
+                }
+            } else if !or_node {
+                return None; // A defence we couldn't prove a mate against (out of nodes)
+            }
+        }
+
+        let (mv, _) = best?;
+        state.make(&mv);
+        let line = self.mating_line(state, depth - 1, ply + 1);
+        state.unmake(&mv, &irs);
+
+        line.map(|mut line| {
+            line.insert(0, mv);
+            line
+        })
+    }
+}
+
+// OR nodes: pn is the smallest pn of the children, dn the sum of their dns - AND nodes the other way around.
+// A proven node is one ply further from the mate than the child(ren) proving it.
+pub fn node_numbers(children: &[(Move, ProofNumbers)], or_node: bool) -> ProofNumbers {
+    let mut min = PN_INF;
+    let mut sum: u64 = 0;
+    for (_, child) in children {
+        let (min_of, sum_of) = if or_node {
+            (child.pn, child.dn)
+        } else {
+            (child.dn, child.pn)
+        };
+        min = cmp::min(min, min_of);
+        sum = cmp::min(sum + sum_of, PN_INF);
+    }
+
+    let mut numbers = if or_node {
+        ProofNumbers::new(min, sum)
+    } else {
+        ProofNumbers::new(sum, min)
+    };
+
+    if numbers.is_proven() {
+        let distances = children
+            .iter()
+            .filter(|(_, child)| child.is_proven())
+            .map(|(_, child)| child.distance);
+        numbers.distance = 1 + if or_node {
+            distances.min().unwrap_or(0)
+        } else {
+            distances.max().unwrap_or(0)
+        };
+    }
+
+    numbers
+}
+
+// Shortest mate in at most max_moves moves for the side to move, if there's one
+pub fn solve_mate(
+    state: &mut State,
+    max_moves: usize,
+    tt: &mut HashTable<ProofNumbers>,
+) -> MateResult {
+    MateSolver::new(tt).solve(state, max_moves)
+}
@@ -0,0 +1,196 @@
+//! Move Picker - yields the legal moves of a node one at a time, in stages
+//! Moves are generated, checked for legality and scored (SEE, PSTEval) only when a stage gets to them,
+//! so at cut-nodes, where the first move or two usually does it, most of that work is never done.
+
+use crate::consts::*;
+use crate::state::*;
+use crate::utils::*;
+
+// Stages, in the order the moves are yielded
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stage {
+    HashMove,
+    GenTactical,
+    GoodTactical, // Tactical moves that don't lose material as per SEE, by MVV-LVA
+    Refutations,  // Killers, then the countermove
+    Quiets,       // By history
+    BadTactical,  // Tactical moves that lose material, in the order they were found out
+    Done,
+}
+
+pub struct MovePicker {
+    pub stage: Stage,
+    pub quiets: bool, // Tactical moves only, if false (quiescence)
+    hash_move: CompactMove,
+    refutations: [CompactMove; NUM_KILLERS + 1],
+    moves: Vec<Move>,
+    scores: Vec<i32>,
+    bad_tactical: Vec<Move>,
+    index: usize,
+}
+
+impl MovePicker {
+    pub fn new(
+        hash_move: CompactMove,
+        killers: [CompactMove; NUM_KILLERS],
+        countermove: CompactMove,
+        quiets: bool,
+    ) -> Self {
+        let mut refutations = [CompactMove::NONE; NUM_KILLERS + 1];
+        refutations[..NUM_KILLERS].copy_from_slice(&killers);
+        refutations[NUM_KILLERS] = countermove;
+
+        MovePicker {
+            stage: Stage::HashMove,
+            quiets,
+            hash_move,
+            refutations,
+            moves: Vec::new(),
+            scores: Vec::new(),
+            bad_tactical: Vec::new(),
+            index: 0,
+        }
+    }
+
+    // Tactical moves only, e.g. for quiescence
+    pub fn tactical() -> Self {
+        MovePicker::new(
+            CompactMove::NONE,
+            [CompactMove::NONE; NUM_KILLERS],
+            CompactMove::NONE,
+            false,
+        )
+    }
+
+    // The next legal move (with see and pst_eval set), if any.
+    // state has to be the position the picker was created for; history is the side to move's history table.
+    pub fn next(&mut self, state: &State, history: &[[i32; 64]; 64]) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GenTactical;
+                    // A quiet hash move has no place in a tactical-only search
+                    if let Some(mut mv) = state.compact_to_move(self.hash_move)
+                        && (self.quiets || state.is_tactical(&mv))
+                    {
+                        state.evaluate_move(&mut mv);
+                        return Some(mv);
+                    }
+                }
+                Stage::GenTactical => {
+                    self.moves.clear();
+                    state.tactical_pl_moves(&mut self.moves);
+                    self.scores = self.moves.iter().map(mvv_lva).collect();
+                    self.index = 0;
+                    self.stage = Stage::GoodTactical;
+                }
+                Stage::GoodTactical => match self.select() {
+                    Some(mut mv) => {
+                        if self.hash_move.matches(&mv) || !state.is_legal(&mv) {
+                            continue;
+                        }
+                        state.evaluate_move(&mut mv);
+                        if mv.see < 0 {
+                            self.bad_tactical.push(mv);
+                            continue;
+                        }
+                        return Some(mv);
+                    }
+                    None => {
+                        self.stage = if self.quiets {
+                            self.moves.clear();
+                            state.quiet_pl_moves(&mut self.moves);
+                            Stage::Refutations
+                        } else {
+                            Stage::BadTactical
+                        };
+                        self.index = 0;
+                    }
+                },
+                Stage::Refutations => {
+                    if self.index > NUM_KILLERS {
+                        self.scores = self
+                            .moves
+                            .iter()
+                            .map(|mv| history[mv.from][mv.to])
+                            .collect();
+                        self.index = 0;
+                        self.stage = Stage::Quiets;
+                        continue;
+                    }
+
+                    let refutation = self.refutations[self.index];
+                    self.index += 1;
+                    if refutation.is_none()
+                        || refutation == self.hash_move
+                        || self.refutations[..self.index - 1].contains(&refutation)
+                    {
+                        continue;
+                    }
+
+                    // Only if it's a (pseudo-legal) quiet move in this position - taking it out of the quiets
+                    if let Some(position) = self.moves.iter().position(|mv| refutation.matches(mv))
+                    {
+                        let mut mv = self.moves.swap_remove(position);
+                        if state.is_legal(&mv) {
+                            state.evaluate_move(&mut mv);
+                            return Some(mv);
+                        }
+                    }
+                }
+                Stage::Quiets => match self.select() {
+                    Some(mut mv) => {
+                        if self.hash_move.matches(&mv) || !state.is_legal(&mv) {
+                            continue;
+                        }
+                        state.evaluate_move(&mut mv);
+                        return Some(mv);
+                    }
+                    None => {
+                        self.index = 0;
+                        self.stage = Stage::BadTactical;
+                    }
+                },
+                Stage::BadTactical => {
+                    if self.index < self.bad_tactical.len() {
+                        self.index += 1;
+                        return Some(self.bad_tactical[self.index - 1]);
+                    }
+                    self.stage = Stage::Done;
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    // Selection sort, one move at a time: the best scored move of the rest
+    fn select(&mut self) -> Option<Move> {
+        if self.index >= self.moves.len() {
+            return None;
+        }
+
+        let mut best = self.index;
+        for i in self.index + 1..self.moves.len() {
+            if self.scores[i] > self.scores[best] {
+                best = i;
+            }
+        }
+        self.moves.swap(self.index, best);
+        self.scores.swap(self.index, best);
+        self.index += 1;
+
+        Some(self.moves[self.index - 1])
+    }
+}
+
+// Most Valuable Victim - Least Valuable Attacker (and what a promotion is worth)
+#[inline]
+pub fn mvv_lva(mv: &Move) -> i32 {
+    let promotion = if mv.promotion == EMPTY {
+        0
+    } else {
+        piece_value_mg(mv.promotion & COLOR_MASK)
+    };
+
+    16 * piece_value_mg(mv.capture & COLOR_MASK) + promotion - (mv.piece & COLOR_MASK) as i32
+}
@@ -0,0 +1,166 @@
+//! Pawn Structure Evaluation
+//! Apart from whether passed pawns are blocked, it all depends on the pawns alone - so it's cached in a pawn hash table,
+//! keyed by State::pawn_hash. Values are from White's point of view.
+
+use crate::consts::*;
+use crate::state::*;
+use crate::utils::*;
+
+// What the pawn hash table keeps per pawn structure
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PawnEval {
+    pub eval_mg: i32,
+    pub eval_eg: i32,
+    pub passed: u64, // Passed pawns of both sides, scored per position (see passed_pawns_eval)
+}
+
+#[inline]
+pub fn north_fill(mut bb: u64) -> u64 {
+    bb |= bb << 8;
+    bb |= bb << 16;
+    bb | bb << 32
+}
+
+#[inline]
+pub fn south_fill(mut bb: u64) -> u64 {
+    bb |= bb >> 8;
+    bb |= bb >> 16;
+    bb | bb >> 32
+}
+
+#[inline]
+pub fn east(bb: u64) -> u64 {
+    (bb & !H_FILE) << 1
+}
+
+#[inline]
+pub fn west(bb: u64) -> u64 {
+    (bb & !A_FILE) >> 1
+}
+
+// One square towards the opponent of color
+#[inline]
+pub fn forward(bb: u64, color: u8) -> u64 {
+    if color == WHITE { bb << 8 } else { bb >> 8 }
+}
+
+// Fills towards the opponent of color (including the squares themselves)
+#[inline]
+pub fn forward_fill(bb: u64, color: u8) -> u64 {
+    if color == WHITE {
+        north_fill(bb)
+    } else {
+        south_fill(bb)
+    }
+}
+
+// Squares in front of the pawns, on their files
+#[inline]
+pub fn front_span(pawns: u64, color: u8) -> u64 {
+    forward_fill(forward(pawns, color), color)
+}
+
+#[inline]
+pub fn pawn_attacks(pawns: u64, color: u8) -> u64 {
+    let front = forward(pawns, color);
+    east(front) | west(front)
+}
+
+// Rank as seen by color (its first rank = 0)
+#[inline]
+pub fn relative_rank(pos: usize, color: u8) -> usize {
+    if color == WHITE { pos / 8 } else { 7 - pos / 8 }
+}
+
+// Pawn structure from scratch
+pub fn pawn_eval(state: &State) -> PawnEval {
+    let white = state.bit_board[WHITE_PAWN];
+    let black = state.bit_board[BLACK_PAWN];
+
+    let (white_mg, white_eg, white_passed) = side_pawn_eval(white, black, WHITE);
+    let (black_mg, black_eg, black_passed) = side_pawn_eval(black, white, BLACK);
+
+    PawnEval {
+        eval_mg: white_mg - black_mg,
+        eval_eg: white_eg - black_eg,
+        passed: white_passed | black_passed,
+    }
+}
+
+// ( mg, eg, passed pawns ) of color's pawns
+fn side_pawn_eval(own: u64, opp: u64, color: u8) -> (i32, i32, u64) {
+    let opp_color = color ^ COLOR;
+    let own_attacks = pawn_attacks(own, color);
+    let opp_attacks = pawn_attacks(opp, opp_color);
+
+    // No enemy pawn in front on the same or an adjacent file - and no pawn of its own in front either
+    let opp_span = front_span(opp, opp_color);
+    let rear = own & front_span(own, opp_color);
+    let passed = own & !(opp_span | east(opp_span) | west(opp_span)) & !rear;
+
+    // Defended by, or side by side with, a pawn of its own
+    let connected = own & (own_attacks | east(own) | west(own));
+
+    // No pawn of its own on the adjacent files
+    let own_files = forward_fill(forward_fill(own, color), opp_color);
+    let isolated = own & !(east(own_files) | west(own_files));
+
+    // Its stop square is attacked by an enemy pawn, and no pawn on an adjacent file can come to its support
+    let unsupported_stops = forward(own & !isolated, color) & !forward_fill(own_attacks, color);
+    let backward = forward(unsupported_stops & opp_attacks, opp_color);
+
+    // Runs of files with pawns
+    let files = (own_files & FIRST_RANK) as u8;
+    let islands = (files & !(files << 1)).count_ones() as i32;
+
+    let num_isolated = isolated.count_ones() as i32;
+    let num_doubled = rear.count_ones() as i32; // Every pawn on a file after the first has one in front of it
+    let num_backward = backward.count_ones() as i32;
+    let extra_islands = (islands - 1).max(0);
+
+    let mut eval_mg = num_isolated * ISOLATED_PAWN_MG
+        + num_doubled * DOUBLED_PAWN_MG
+        + num_backward * BACKWARD_PAWN_MG
+        + extra_islands * PAWN_ISLAND_MG;
+    let mut eval_eg = num_isolated * ISOLATED_PAWN_EG
+        + num_doubled * DOUBLED_PAWN_EG
+        + num_backward * BACKWARD_PAWN_EG
+        + extra_islands * PAWN_ISLAND_EG;
+
+    let mut bb = connected;
+    while bb != 0 {
+        let rank = relative_rank(pop_lsb_pos(&mut bb), color);
+        eval_mg += CONNECTED_PAWN_MG[rank];
+        eval_eg += CONNECTED_PAWN_EG[rank];
+    }
+
+    (eval_mg, eval_eg, passed)
+}
+
+// ( mg, eg ) of the passed pawns: by rank, more if defended by a pawn, less if something stands in their way
+pub fn passed_pawns_eval(state: &State, passed: u64) -> (i32, i32) {
+    let mut eval_mg = 0;
+    let mut eval_eg = 0;
+
+    let mut bb = passed;
+    while bb != 0 {
+        let pos = pop_lsb_pos(&mut bb);
+        let color = state.simple_board[pos] & COLOR;
+        let rank = relative_rank(pos, color);
+
+        let mut pct = 100;
+        if pawn_attacks(state.bit_board[color | PAWN], color) & (1 << pos) != 0 {
+            pct = pct * SUPPORTED_PASSED_PAWN_PCT / 100;
+        }
+        let stop = forward(1 << pos, color);
+        if (state.bit_board[WHITE_ALL] | state.bit_board[BLACK_ALL]) & stop != 0 {
+            pct = pct * BLOCKED_PASSED_PAWN_PCT / 100;
+        }
+
+        let sign = if color == WHITE { 1 } else { -1 };
+        eval_mg += sign * PASSED_PAWN_MG[rank] * pct / 100;
+        eval_eg += sign * PASSED_PAWN_EG[rank] * pct / 100;
+    }
+
+    (eval_mg, eval_eg)
+}
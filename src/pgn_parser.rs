@@ -4,8 +4,8 @@ use crate::consts::*;
 use crate::state::*;
 use crate::utils::*;
 use std::fs::File;
-use std::io::prelude::*;
 use std::io::BufReader;
+use std::io::prelude::*;
 
 // Game Start Delineator
 pub const PGN_DELINEATOR: &str = "[Event";
@@ -249,7 +249,7 @@ pub fn parse_move(mv_str: &str, state: &State) -> Result<Move, String> {
                         let disamb = match mv_str_mut.chars().next() {
                             Some(file_actual) => file_actual,
                             None => {
-                                return Err("disamb: mv_str_mut[ 0 ] out of bounds!".to_string())
+                                return Err("disamb: mv_str_mut[ 0 ] out of bounds!".to_string());
                             }
                         };
 
@@ -283,7 +283,7 @@ pub fn parse_move(mv_str: &str, state: &State) -> Result<Move, String> {
 
                 let final_size = poss_filtered.len();
                 if final_size == 1 {
-                    match poss_filtered.iter_mut().nth(0) {
+                    match poss_filtered.first_mut() {
                         Some(poss_filtered_actual) => {
                             state.evaluate_move(poss_filtered_actual);
                             Ok(*poss_filtered_actual)
@@ -297,22 +297,30 @@ pub fn parse_move(mv_str: &str, state: &State) -> Result<Move, String> {
                     ))
                 }
             } else if num_poss == 1 {
-                match possibilities.iter_mut().nth(0) {
+                match possibilities.first_mut() {
                     Some(possibilities_actual) => {
                         state.evaluate_move(possibilities_actual);
                         Ok(*possibilities_actual)
                     }
-                    None => {
-                        return Err("possibilities: possibilities[ 0 ] out of bounds!".to_string())
-                    }
+                    None => Err("possibilities: possibilities[ 0 ] out of bounds!".to_string()),
                 }
             } else {
-                return Err(format!("Illegal move: {}", mv_str));
+                Err(format!("Illegal move: {}", mv_str))
             }
         }
     }
 }
 
+// Parse a move given in Long Algebraic Notation (e2e4, e7e8q, e1g1)
+pub fn parse_long_algebraic(mv_str: &str, state: &State) -> Result<Move, String> {
+    let (legal_moves, _) = state.node_info();
+
+    legal_moves
+        .into_iter()
+        .find(|mv| mv.long_algebraic() == mv_str)
+        .ok_or(format!("Illegal move: {}", mv_str))
+}
+
 // Parse pgn, do some checks and return the GameList
 pub fn parse_pgn(path: &str) -> Vec<Game> {
     let file = match File::open(path) {
@@ -333,7 +341,7 @@ pub fn parse_pgn(path: &str) -> Vec<Game> {
     let game_iter = file_string.split(PGN_DELINEATOR).skip(1);
     for pgn in game_iter {
         let pgn = pgn.trim();
-        let move_text = pgn.split(']').last().unwrap();
+        let move_text = pgn.split(']').next_back().unwrap();
         let mut curr_iter = pgn.split(']').skip(1);
 
         // Seven Tag Roster - we already ignored 'Event'
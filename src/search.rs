@@ -1,34 +1,169 @@
 //! Game Tree Search
 
 use crate::consts::*;
+use crate::evaluation::*;
 use crate::hashtables::*;
+use crate::movepick::*;
 use crate::state::*;
+use crate::timeman::*;
 use std::cmp;
 use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::thread;
+use std::time::Instant;
 
-// Evaluation Type
+// Evaluation Type: The kind of bound the value is on the true evaluation
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum EvalType {
-    Alpha,
+    Lower, // Failed high (beta cutoff)
+    Upper, // Failed low (no move raised alpha)
     Exact,
 }
 
-// Evaluation Result
+// Evaluation Result: This is what we store in the transposition table
+// The value is stored as an i16 (|value| <= MATE_VALUE) to fit four HashItems in a cache line
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Eval {
     pub eval_type: EvalType,
-    pub value: i32,
+    pub value: i16,
+    pub best_move: CompactMove,
 }
 
 impl Default for Eval {
     fn default() -> Self {
         Eval {
-            eval_type: EvalType::Alpha,
-            value: -INF_VALUE,
+            eval_type: EvalType::Upper,
+            value: 0,
+            best_move: CompactMove::NONE,
         }
     }
 }
 
+impl Eval {
+    pub fn new(eval_type: EvalType, value: i32, best_move: CompactMove) -> Self {
+        Eval {
+            eval_type,
+            value: value as i16,
+            best_move,
+        }
+    }
+
+    #[inline]
+    pub fn value(&self) -> i32 {
+        self.value as i32
+    }
+
+    // Can we cut off with this, given the window?
+    #[inline]
+    pub fn cutoff(&self, alpha: i32, beta: i32) -> bool {
+        match self.eval_type {
+            EvalType::Exact => true,
+            EvalType::Lower => beta <= self.value(),
+            EvalType::Upper => self.value() <= alpha,
+        }
+    }
+}
+
+// Packed as value (16 bits) | best_move (16 bits) | eval_type (2 bits)
+impl Pack for Eval {
+    #[inline]
+    fn pack(&self) -> u64 {
+        self.value as u16 as u64 | (self.best_move.0 as u64) << 16 | (self.eval_type as u64) << 32
+    }
+
+    #[inline]
+    fn unpack(data: u64) -> Self {
+        Eval {
+            eval_type: match (data >> 32) & 3 {
+                0 => EvalType::Lower,
+                1 => EvalType::Upper,
+                _ => EvalType::Exact,
+            },
+            value: data as u16 as i16,
+            best_move: CompactMove((data >> 16) as u16),
+        }
+    }
+}
+
+// For the same position, don't replace a deeper search unless we have an Exact value
+impl UpdateHash<Eval> for HashItem<Eval> {
+    #[inline]
+    fn update(&self, _hash: u64, depth: usize, value: Eval) -> bool {
+        self.depth as usize <= depth || value.eval_type == EvalType::Exact
+    }
+}
+
+// Mate scores are relative to the ply they are found at: the faster the mate, the higher the score
+#[inline]
+pub fn mated_in(ply: usize) -> i32 {
+    -MATE_VALUE + ply as i32
+}
+
+#[inline]
+pub fn mate_in(ply: usize) -> i32 {
+    MATE_VALUE - ply as i32
+}
+
+#[inline]
+pub fn is_mate(value: i32) -> bool {
+    value.abs() >= MATE_BOUND
+}
+
+// The TT stores mate scores relative to the position (not the root), as the position might be reached at a different ply
+#[inline]
+pub fn value_to_tt(value: i32, ply: usize) -> i32 {
+    if value >= MATE_BOUND {
+        value + ply as i32
+    } else if value <= -MATE_BOUND {
+        value - ply as i32
+    } else {
+        value
+    }
+}
+
+#[inline]
+pub fn value_from_tt(value: i32, ply: usize) -> i32 {
+    if value >= MATE_BOUND {
+        value - ply as i32
+    } else if value <= -MATE_BOUND {
+        value + ply as i32
+    } else {
+        value
+    }
+}
+
+// Score as reported to the user: centipawns, or moves to mate (negative if we are getting mated)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Score {
+    Cp(i32),
+    Mate(i32),
+}
+
+impl Score {
+    pub fn new(value: i32) -> Self {
+        if value >= MATE_BOUND {
+            Score::Mate((MATE_VALUE - value + 1) / 2)
+        } else if value <= -MATE_BOUND {
+            Score::Mate(-(MATE_VALUE + value + 1) / 2)
+        } else {
+            Score::Cp(value)
+        }
+    }
+}
+
+// As expected by UCI: "cp <x>" or "mate <y>"
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Score::Cp(cp) => write!(f, "cp {}", cp),
+            Score::Mate(moves) => write!(f, "mate {}", moves),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Variation {
     pub eval: i32,
     pub move_list: VecDeque<Move>,
@@ -42,6 +177,16 @@ impl Variation {
         }
     }
 
+    // The move list in Long Algebraic Notation, space separated
+    pub fn pv_string(&self) -> String {
+        let pv: Vec<String> = self
+            .move_list
+            .iter()
+            .map(|mv| mv.long_algebraic())
+            .collect();
+        pv.join(" ")
+    }
+
     pub fn max_assign(&mut self, mv: &Move, var: Variation) {
         if self.eval < -var.eval {
             self.eval = -var.eval;
@@ -62,6 +207,23 @@ pub struct SearchStats {
     pub beta_cutoff_qs: u64,
     pub hash_hit: u64,
     pub hash_cutoff: u64,
+    pub pvs_null_window: u64,  // Moves searched with a null window
+    pub pvs_research: u64,     // ... that had to be re-searched with the full window
+    pub null_move: u64,        // Null moves tried
+    pub null_move_cutoff: u64, // ... that failed high
+    pub lmr: u64,              // Moves searched to a reduced depth
+    pub lmr_research: u64,     // ... that had to be re-searched to the full depth
+    pub lmp: u64,              // Moves pruned by Late Move Pruning
+    pub repetition: u64,       // Nodes scored as a draw by repetition
+    pub checks_qs: u64,        // Quiet checks searched in quiescence
+    pub delta_pruned_qs: u64,  // Captures skipped by Delta Pruning
+    pub see_pruned_qs: u64,    // Moves skipped for losing material as per SEE
+    pub rfp: u64,              // Nodes cut off by Reverse Futility Pruning
+    pub futility: u64,         // Moves pruned by Futility Pruning
+    pub razoring: u64,         // Nodes resolved by Razoring
+    pub check_ext: u64,        // Checks extended
+    pub singular: u64,         // Singular Extension searches
+    pub singular_ext: u64,     // ... that found the hash move singular, and extended it
 }
 
 impl Default for SearchStats {
@@ -82,52 +244,515 @@ impl SearchStats {
             beta_cutoff_qs: 0,
             hash_hit: 0,
             hash_cutoff: 0,
+            pvs_null_window: 0,
+            pvs_research: 0,
+            null_move: 0,
+            null_move_cutoff: 0,
+            lmr: 0,
+            lmr_research: 0,
+            lmp: 0,
+            repetition: 0,
+            checks_qs: 0,
+            delta_pruned_qs: 0,
+            see_pruned_qs: 0,
+            rfp: 0,
+            futility: 0,
+            razoring: 0,
+            check_ext: 0,
+            singular: 0,
+            singular_ext: 0,
+        }
+    }
+
+    // Total number of nodes visited (negamax + quiescence)
+    // Leaf nodes at depth zero (max_depth) are counted by quiescence
+    pub fn nodes(&self) -> u64 {
+        self.end
+            + self.middle
+            + self.hash_cutoff
+            + self.end_qs
+            + self.middle_qs
+            + self.quiet_qs
+            + self.beta_cutoff_qs
+            + self.repetition
+    }
+}
+
+// Late Move Reductions, indexed by [ depth ][ move number ]
+// Grows with the log of both: the deeper the search and the later the move, the more we reduce.
+pub static LMR_TABLE: LazyLock<[[usize; LMR_MAX_MOVES]; MAX_DEPTH + 1]> = LazyLock::new(|| {
+    let mut table = [[0; LMR_MAX_MOVES]; MAX_DEPTH + 1];
+    for (depth, row) in table.iter_mut().enumerate().skip(1) {
+        for (index, reduction) in row.iter_mut().enumerate().skip(1) {
+            *reduction =
+                (LMR_BASE + (depth as f64).ln() * (index as f64).ln() / LMR_DIVISOR) as usize;
+        }
+    }
+    table
+});
+
+// Search Options: Switch search techniques on/off (and set their margins), e.g. to measure their effect on the node counts
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SearchOptions {
+    pub pvs: bool,       // Principal Variation Search
+    pub null_move: bool, // Null Move Pruning
+    pub lmr: bool,       // Late Move Reductions
+    pub lmp: bool,       // Late Move Pruning
+    pub qs_checks: bool, // Quiet checks at the first ply of quiescence (off by default: finding them costs a make per quiet move)
+    pub delta: bool,     // Delta Pruning in quiescence
+    pub qs_see: bool,    // SEE Pruning in quiescence
+    pub rfp: bool,       // Reverse Futility Pruning
+    pub futility: bool,  // Futility Pruning
+    pub razoring: bool,  // Razoring
+    pub check_ext: bool, // Check Extensions
+    pub singular: bool,  // Singular Extensions
+    pub rfp_margin: i32,
+    pub futility_margin: i32,
+    pub razor_margin: i32,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SearchOptions {
+    pub fn new() -> Self {
+        SearchOptions {
+            pvs: true,
+            null_move: true,
+            lmr: true,
+            lmp: true,
+            qs_checks: false,
+            delta: true,
+            qs_see: true,
+            rfp: true,
+            futility: true,
+            razoring: true,
+            check_ext: true,
+            singular: true,
+            rfp_margin: RFP_MARGIN,
+            futility_margin: FUTILITY_MARGIN,
+            razor_margin: RAZOR_MARGIN,
+        }
+    }
+
+    // Plain alpha-beta (with quiescence): every technique that prunes, reduces or extends switched off
+    pub fn none() -> Self {
+        SearchOptions {
+            pvs: false,
+            null_move: false,
+            lmr: false,
+            lmp: false,
+            qs_checks: false,
+            delta: false,
+            qs_see: false,
+            rfp: false,
+            futility: false,
+            razoring: false,
+            check_ext: false,
+            singular: false,
+            ..Self::new()
         }
     }
 }
 
-pub fn profile(state: &mut State, depth: usize) {
+// Search Limits: The search ends as soon as any of these is hit (None / empty for no limit)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub depth: Option<usize>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<u64>,         // In milliseconds
+    pub mate: Option<usize>,           // Stop once we find a mate in ( at most ) these many moves
+    pub searchmoves: Vec<CompactMove>, // Only search these root moves
+}
+
+impl SearchLimits {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Is there anything (other than MAX_DEPTH) to end the search?
+    pub fn is_bounded(&self) -> bool {
+        self.depth.is_some()
+            || self.nodes.is_some()
+            || self.movetime.is_some()
+            || self.mate.is_some()
+    }
+
+    pub fn max_depth(&self) -> usize {
+        cmp::min(self.depth.unwrap_or(MAX_DEPTH), MAX_DEPTH)
+    }
+}
+
+// What ended the search
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Depth,
+    Nodes,
+    MoveTime,
+    Mate,
+    Time,     // Clock (TimeManager)
+    Stopped,  // From the outside, e.g. by the GUI
+    GameOver, // Nothing to search at the root
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            StopReason::Depth => "depth limit",
+            StopReason::Nodes => "node limit",
+            StopReason::MoveTime => "move time",
+            StopReason::Mate => "mate found",
+            StopReason::Time => "out of time",
+            StopReason::Stopped => "stopped",
+            StopReason::GameOver => "game over",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+// Search Context: Everything that's carried through the search, apart from the State and the TT
+pub struct SearchContext<E: Evaluator = HeuristicEvaluator> {
+    pub evaluator: E,
+    pub options: SearchOptions,
+    pub limits: SearchLimits,
+    pub stats: SearchStats,
+    pub stop: Arc<AtomicBool>, // Set to abort the search
+    pub stop_reason: Option<StopReason>,
+    pub start: Instant,
+    pub time: Option<TimeManager>,
+    pub next_time_check: u64, // Node count at which to check the limits next
+
+    // Lazy SMP
+    pub thread_id: usize,      // 0 for the main thread
+    pub nodes: Arc<AtomicU64>, // Nodes searched by all the threads (synced every TIME_CHECK_INTERVAL nodes)
+    pub synced_nodes: u64,     // Nodes of this thread already added to the above
+
+    // Move ordering
+    pub killers: [[CompactMove; NUM_KILLERS]; MAX_DEPTH + 1], // Quiet moves that caused a beta cutoff, per ply
+    pub history: [[[i32; 64]; 64]; 2], // Butterfly history: [ side ][ from ][ to ]
+    pub countermoves: [[CompactMove; 64]; 12], // Quiet move that refuted the previous move: [ piece ][ to ]
+    pub path: [Option<Move>; MAX_DEPTH + 1],   // Move made at every ply (None for a null move)
+
+    pub excluded: Vec<CompactMove>, // Root moves not to search (MultiPV)
+
+    // Extensions
+    pub root_depth: usize, // Depth of the current iteration
+    pub singular_move: [CompactMove; MAX_DEPTH + 1], // Hash move left out by a Singular Extension search, per ply
+}
+
+impl Default for SearchContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SearchContext {
+    pub fn new() -> Self {
+        Self::with_evaluator(HeuristicEvaluator::new())
+    }
+}
+
+impl<E: Evaluator> SearchContext<E> {
+    pub fn with_evaluator(evaluator: E) -> Self {
+        SearchContext {
+            evaluator,
+            options: SearchOptions::new(),
+            limits: SearchLimits::new(),
+            stats: SearchStats::new(),
+            stop: Arc::new(AtomicBool::new(false)),
+            stop_reason: None,
+            start: Instant::now(),
+            time: None,
+            next_time_check: 0,
+            thread_id: 0,
+            nodes: Arc::new(AtomicU64::new(0)),
+            synced_nodes: 0,
+            killers: [[CompactMove::NONE; NUM_KILLERS]; MAX_DEPTH + 1],
+            history: [[[0; 64]; 64]; 2],
+            countermoves: [[CompactMove::NONE; 64]; 12],
+            path: [None; MAX_DEPTH + 1],
+            excluded: Vec::new(),
+            root_depth: 0,
+            singular_move: [CompactMove::NONE; MAX_DEPTH + 1],
+        }
+    }
+
+    #[inline]
+    pub fn is_killer(&self, ply: usize, mv: &Move) -> bool {
+        self.killers[ply].iter().any(|killer| killer.matches(mv))
+    }
+
+    // The quiet move that last refuted the move leading to this ply
+    #[inline]
+    pub fn countermove(&self, ply: usize) -> CompactMove {
+        match ply.checked_sub(1).and_then(|prev_ply| self.path[prev_ply]) {
+            Some(prev) => self.countermoves[prev.piece as usize][prev.to],
+            None => CompactMove::NONE,
+        }
+    }
+
+    // A quiet move caused a beta cutoff: reward it, and penalize the quiet moves searched before it
+    pub fn update_quiet_stats(&mut self, ply: usize, depth: usize, mv: &Move, failed: &[Move]) {
+        let compact = mv.compact();
+        let killers = &mut self.killers[ply];
+        if killers[0] != compact {
+            killers.rotate_right(1);
+            killers[0] = compact;
+        }
+
+        if let Some(prev) = ply.checked_sub(1).and_then(|prev_ply| self.path[prev_ply]) {
+            self.countermoves[prev.piece as usize][prev.to] = compact;
+        }
+
+        let bonus = cmp::min((depth * depth) as i32, HISTORY_MAX);
+        self.update_history(mv, bonus);
+        for failed_mv in failed {
+            self.update_history(failed_mv, -bonus);
+        }
+    }
+
+    // Bonuses shrink as the entry approaches HISTORY_MAX, so the entries stay within +/- HISTORY_MAX
+    #[inline]
+    fn update_history(&mut self, mv: &Move, bonus: i32) {
+        let entry = &mut self.history[(mv.piece & COLOR) as usize][mv.from][mv.to];
+        *entry += bonus - *entry * bonus.abs() / HISTORY_MAX;
+    }
+
+    // Make / unmake a move, keeping the evaluator in sync
+    #[inline]
+    pub fn make(&mut self, state: &mut State, mv: &Move) {
+        state.make(mv);
+        self.evaluator.make(state, mv);
+    }
+
+    #[inline]
+    pub fn unmake(&mut self, state: &mut State, mv: &Move, irs: &IRState) {
+        state.unmake(mv, irs);
+        self.evaluator.unmake(state, mv);
+    }
+
+    #[inline]
+    pub fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    // Context for a Lazy SMP helper thread: same options, but its own stop flag - the main thread enforces the limits
+    pub fn helper(&self, thread_id: usize, stop: Arc<AtomicBool>) -> Self {
+        let mut ctx = SearchContext::with_evaluator(self.evaluator.clone());
+        ctx.options = self.options;
+        ctx.limits.depth = self.limits.depth;
+        ctx.limits.searchmoves = self.limits.searchmoves.clone();
+        ctx.thread_id = thread_id;
+        ctx.stop = stop;
+        ctx.nodes = self.nodes.clone();
+        ctx
+    }
+
+    // Stop the search, remembering the first reason to do so
+    pub fn stop_with(&mut self, reason: StopReason) {
+        self.stop_reason.get_or_insert(reason);
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    // Every TIME_CHECK_INTERVAL nodes (or right at the node limit): sync the node count, and stop the search once we hit
+    // the node limit, the move time, or the hard limit of the clock.
+    #[inline]
+    pub fn check_limits(&mut self) {
+        let nodes = self.stats.nodes();
+        if nodes >= self.next_time_check {
+            self.next_time_check = nodes + TIME_CHECK_INTERVAL;
+            self.sync_nodes();
+
+            if let Some(max_nodes) = self.limits.nodes {
+                let total_nodes = self.total_nodes();
+                if max_nodes <= total_nodes {
+                    self.stop_with(StopReason::Nodes);
+                } else {
+                    self.next_time_check =
+                        cmp::min(self.next_time_check, nodes + (max_nodes - total_nodes));
+                }
+            }
+
+            if let Some(movetime) = self.limits.movetime
+                && movetime.saturating_sub(MOVE_OVERHEAD) <= self.start.elapsed().as_millis() as u64
+            {
+                self.stop_with(StopReason::MoveTime);
+            }
+
+            if let Some(time) = &self.time
+                && time.hard_limit_exceeded()
+            {
+                self.stop_with(StopReason::Time);
+            }
+        }
+    }
+
+    // Can this move be searched at the root?
+    #[inline]
+    pub fn is_root_move(&self, mv: &Move) -> bool {
+        (self.limits.searchmoves.is_empty()
+            || self.limits.searchmoves.iter().any(|x| x.matches(mv)))
+            && !self.excluded.iter().any(|x| x.matches(mv))
+    }
+
+    // Repetitions can't span a null move: plies made since the last one in the path (if any)
+    #[inline]
+    pub fn repetition_window(&self, ply: usize) -> usize {
+        self.path[..ply]
+            .iter()
+            .rev()
+            .position(|mv| mv.is_none())
+            .unwrap_or(usize::MAX)
+    }
+
+    // After every completed iteration: Should we stop, given the limits? (the rest are checked during the search)
+    pub fn iteration_done(&mut self, depth: usize, best: &Variation) -> bool {
+        if best.move_list.is_empty() {
+            self.stop_reason = Some(StopReason::GameOver);
+        } else if let Some(mate) = self.limits.mate
+            && let Score::Mate(moves) = Score::new(best.eval)
+            && 0 < moves
+            && moves as usize <= mate
+        {
+            self.stop_reason = Some(StopReason::Mate);
+        } else if let Some(time) = &mut self.time
+            && time.iteration_done(best)
+        {
+            self.stop_reason = Some(StopReason::Time);
+        } else if self.limits.max_depth() <= depth {
+            self.stop_reason = Some(StopReason::Depth);
+        }
+
+        self.stop_reason.is_some()
+    }
+
+    // After an aborted iteration: if it wasn't any of the limits, we were stopped from the outside
+    pub fn aborted(&mut self) {
+        self.stop_reason.get_or_insert(StopReason::Stopped);
+    }
+
+    pub fn sync_nodes(&mut self) {
+        let nodes = self.stats.nodes();
+        self.nodes
+            .fetch_add(nodes - self.synced_nodes, Ordering::Relaxed);
+        self.synced_nodes = nodes;
+    }
+
+    // Nodes searched by all the threads
+    pub fn total_nodes(&self) -> u64 {
+        self.nodes.load(Ordering::Relaxed) + self.stats.nodes() - self.synced_nodes
+    }
+}
+
+pub fn profile<E: Evaluator>(state: &mut State, depth: usize, evaluator: &mut E) {
     if depth == 0 {
-        let _ = state.pst_eval();
+        let _ = evaluator.eval(state);
     } else {
         let (legal_moves, _) = state.node_info();
         let irs = state.ir_state();
 
         for mv in &legal_moves {
             state.make(mv);
-            profile(state, depth - 1);
+            evaluator.make(state, mv);
+            profile(state, depth - 1, evaluator);
             state.unmake(mv, &irs);
+            evaluator.unmake(state, mv);
         }
     }
 }
 
-pub fn quiescence(state: &mut State, mut alpha: i32, beta: i32, stats: &mut SearchStats) -> i32 {
-    let (legal_moves, status) = state.node_info();
+// Quiescence Search: Only tactical moves (and quiet checks at the first ply, qs_ply == 0), till the position is quiet.
+// When in check, all evasions are searched, as standing pat isn't an option.
+pub fn quiescence<E: Evaluator>(
+    state: &mut State,
+    ply: usize,
+    qs_ply: usize,
+    mut alpha: i32,
+    beta: i32,
+    ctx: &mut SearchContext<E>,
+) -> i32 {
+    ctx.check_limits();
+    if ctx.stopped() {
+        return DRAW_VALUE;
+    }
 
-    if status == Status::Ongoing {
-        let mut eval = state.pst_eval();
+    // Checkmate is found out by searching the evasions; stalemate isn't looked for here, it's too rare to pay for
+    if state.draw_status() == Status::Ongoing {
+        let in_check = state.num_checks > 0;
+        let stand_pat = ctx.evaluator.eval(state);
 
-        if beta <= eval {
+        if !in_check && beta <= stand_pat {
             // Assuming that we are not in Zugzwang, the "Stand Pat" is a lower bound on the eval.
             // FIXME: Ideally, we might want to make a null_move and then return that score - given we might implement some sort of Tempo into the static_eval?
             // Failing soft
-            stats.beta_cutoff_qs += 1;
-            eval
+            ctx.stats.beta_cutoff_qs += 1;
+            stand_pat
         } else {
-            alpha = cmp::max(alpha, eval);
+            let mut eval = if in_check {
+                -INF_VALUE
+            } else {
+                alpha = cmp::max(alpha, stand_pat);
+                stand_pat
+            };
             let irs = state.ir_state();
-            let tactical_moves = state.tactical_moves(&legal_moves);
+            let side = state.to_move;
+            let pst_eval = state.pst_eval();
+            let checks = !in_check && qs_ply == 0 && ctx.options.qs_checks;
 
-            if tactical_moves.is_empty() {
-                stats.quiet_qs += 1;
+            let mut picker = if in_check || checks {
+                MovePicker::new(
+                    CompactMove::NONE,
+                    [CompactMove::NONE; NUM_KILLERS],
+                    CompactMove::NONE,
+                    true,
+                )
             } else {
-                stats.middle_qs += 1;
-            }
+                MovePicker::tactical()
+            };
+            let mut num_moves = 0;
+
+            while let Some(mv) = picker.next(state, &ctx.history[side as usize]) {
+                let tactical = state.is_tactical(&mv);
+                num_moves += 1;
+
+                if !in_check {
+                    // SEE Pruning: Losing captures (and checks hanging the piece) are unlikely to help
+                    if ctx.options.qs_see && mv.see < 0 {
+                        ctx.stats.see_pruned_qs += 1;
+                        continue;
+                    }
+
+                    // Delta Pruning: Even the eval after the capture (plus a safety margin) can't raise alpha
+                    // The capture's gain is estimated by the PST, whatever the evaluator.
+                    if ctx.options.delta
+                        && tactical
+                        && !mv.is_promotion()
+                        && stand_pat + mv.pst_eval.eval(side) - pst_eval + DELTA_MARGIN <= alpha
+                    {
+                        ctx.stats.delta_pruned_qs += 1;
+                        continue;
+                    }
+                }
+
+                ctx.make(state, &mv);
 
-            for mv in &tactical_moves {
-                state.make(mv);
-                eval = cmp::max(eval, -quiescence(state, -beta, -alpha, stats));
-                state.unmake(mv, &irs);
+                // Quiet moves only if they give check
+                if !in_check && !tactical {
+                    if state.num_checks == 0 {
+                        ctx.unmake(state, &mv, &irs);
+                        continue;
+                    }
+                    ctx.stats.checks_qs += 1;
+                }
+
+                eval = cmp::max(
+                    eval,
+                    -quiescence(state, ply + 1, qs_ply + 1, -beta, -alpha, ctx),
+                );
+                ctx.unmake(state, &mv, &irs);
 
                 alpha = cmp::max(alpha, eval);
 
@@ -137,79 +762,611 @@ pub fn quiescence(state: &mut State, mut alpha: i32, beta: i32, stats: &mut Sear
                 }
             }
 
-            eval
+            if num_moves > 0 {
+                ctx.stats.middle_qs += 1;
+                eval
+            } else if in_check {
+                ctx.stats.end_qs += 1;
+                mated_in(ply)
+            } else {
+                ctx.stats.quiet_qs += 1;
+                eval
+            }
         }
     } else {
-        stats.end_qs += 1;
-        if status == Status::Checkmate {
-            -MATE_VALUE
-        } else {
-            DRAW_VALUE
-        }
+        ctx.stats.end_qs += 1;
+        DRAW_VALUE
     }
 }
 
-pub fn negamax(
+pub fn negamax<E: Evaluator>(
     state: &mut State,
     depth: usize,
+    ply: usize,
     mut alpha: i32,
-    beta: i32,
-    stats: &mut SearchStats,
-    tt: &mut HashTable<Eval>,
+    mut beta: i32,
+    ctx: &mut SearchContext<E>,
+    tt: &SharedHashTable<Eval>,
 ) -> Variation {
-    let (legal_moves, status) = state.node_info();
+    ctx.check_limits();
+    if ctx.stopped() {
+        return Variation::terminal(DRAW_VALUE);
+    }
+
+    // Repetitions (and the fifty move rule, via node_info) draw; but at the root, we need a move
+    if ply > 0 && state.is_search_repetition(ply, ctx.repetition_window(ply)) {
+        ctx.stats.repetition += 1;
+        return Variation::terminal(DRAW_VALUE);
+    }
+
+    // Mate Distance Pruning: We can't do better than mating on the next move, or worse than getting mated right here
+    if ply > 0 {
+        alpha = cmp::max(alpha, mated_in(ply));
+        beta = cmp::min(beta, mate_in(ply + 1));
+        if beta <= alpha {
+            return Variation::terminal(alpha);
+        }
+    }
+
+    if ply == 0 {
+        ctx.root_depth = depth;
+    }
+
+    // The entry (and best move) is only meaningful if every move was searched - not so at the root with
+    // some moves excluded, or in a Singular Extension search, which leaves out the hash move.
+    let singular_move = ctx.singular_move[ply];
+    let excluding = !singular_move.is_none()
+        || ply == 0 && !(ctx.excluded.is_empty() && ctx.limits.searchmoves.is_empty());
 
-    if status == Status::Ongoing {
-        if let Some(hashed) = tt.get(state.hash, depth) {
-            stats.hash_hit += 1;
-            if beta <= hashed.value
-                || (hashed.eval_type == EvalType::Exact && hashed.value <= alpha)
+    // Checkmate and stalemate are found out once we run out of moves to search. A drawn root is still
+    // searched, so that there is a move to play
+    if ply == 0 || state.draw_status() == Status::Ongoing {
+        // Any entry searched at least as deep can cut off; the best move is useful regardless
+        let mut tt_move = CompactMove::NONE;
+        let mut tt_entry: Option<(usize, Eval)> = None;
+        if let Some((hashed_depth, hashed)) = tt.probe(state.hash) {
+            let hashed = Eval::new(
+                hashed.eval_type,
+                value_from_tt(hashed.value(), ply),
+                hashed.best_move,
+            );
+            ctx.stats.hash_hit += 1;
+            tt_move = hashed.best_move;
+            tt_entry = Some((hashed_depth, hashed));
+
+            // No hash cutoffs at the root - we need a move; nor when the entry is this very node's, with the hash move
+            if ply > 0
+                && singular_move.is_none()
+                && hashed_depth >= depth
+                && hashed.cutoff(alpha, beta)
             {
-                stats.hash_cutoff += 1;
-                return Variation::terminal(hashed.value);
+                ctx.stats.hash_cutoff += 1;
+                return Variation::terminal(hashed.value());
             }
         }
 
         if depth == 0 {
-            stats.max_depth += 1;
-            Variation::terminal(quiescence(state, alpha, beta, stats))
+            ctx.stats.max_depth += 1;
+            Variation::terminal(quiescence(state, ply, 0, alpha, beta, ctx))
         } else {
-            stats.middle += 1;
+            // A Singular Extension search revisits a node that's already counted
+            if singular_move.is_none() {
+                ctx.stats.middle += 1;
+            }
             let irs = state.ir_state();
+            let in_check = state.num_checks > 0;
+            let pv_node = beta - alpha > 1;
+            let static_eval = ctx.evaluator.eval(state);
+
+            // Reverse Futility Pruning: Far enough above beta, a shallow search is unlikely to bring us back down
+            // Neither this nor Razoring in a Singular Extension search - the extension should rest on a search, not a guess.
+            if ctx.options.rfp
+                && !pv_node
+                && singular_move.is_none()
+                && !in_check
+                && depth <= RFP_MAX_DEPTH
+                && !is_mate(beta)
+                && static_eval - ctx.options.rfp_margin * depth as i32 >= beta
+            {
+                ctx.stats.rfp += 1;
+                return Variation::terminal(static_eval - ctx.options.rfp_margin * depth as i32);
+            }
+
+            // Razoring: Far enough below alpha, only captures could save us - if quiescence agrees, we are done
+            if ctx.options.razoring
+                && !pv_node
+                && singular_move.is_none()
+                && !in_check
+                && depth <= RAZOR_MAX_DEPTH
+                && !is_mate(alpha)
+                && static_eval + ctx.options.razor_margin * depth as i32 <= alpha
+            {
+                let eval = quiescence(state, ply, 0, alpha, alpha + 1, ctx);
+                if eval <= alpha {
+                    ctx.stats.razoring += 1;
+                    return Variation::terminal(eval);
+                }
+            }
+
+            // Null Move Pruning: If we are doing well enough to fail high even after passing the turn, we'd fail high with a real move too.
+            // This doesn't hold in Zugzwang - so not when in check, and not without enough non-pawn material.
+            // Never two null moves in a row, and not at the root (we need a move).
+            if ctx.options.null_move
+                && ply > 0
+                && singular_move.is_none()
+                && ctx.path[ply - 1].is_some()
+                && depth >= NULL_MOVE_MIN_DEPTH
+                && !in_check
+                && state.pst_eval.npm >= NULL_MOVE_MIN_NPM
+                && state.has_non_pawn_material(state.to_move)
+                && static_eval >= beta
+            {
+                let adaptive_depth = if state.pst_eval.npm < EG_NPM_LIMIT {
+                    NULL_MOVE_ADAPTIVE_DEPTH_EG
+                } else {
+                    NULL_MOVE_ADAPTIVE_DEPTH
+                };
+                let r = NULL_MOVE_R + (depth > adaptive_depth) as usize;
+
+                ctx.stats.null_move += 1;
+                state.make_null();
+                ctx.path[ply] = None;
+                let child = negamax(
+                    state,
+                    (depth - 1).saturating_sub(r),
+                    ply + 1,
+                    -beta,
+                    -beta + 1,
+                    ctx,
+                    tt,
+                );
+                state.unmake_null(&irs);
+
+                if ctx.stopped() {
+                    return Variation::terminal(DRAW_VALUE);
+                }
+
+                // Don't trust a mate score from a position where we passed
+                if beta <= -child.eval {
+                    ctx.stats.null_move_cutoff += 1;
+                    return Variation::terminal(if is_mate(-child.eval) {
+                        beta
+                    } else {
+                        -child.eval
+                    });
+                }
+            }
+
+            // Extensions are only allowed this far into the tree
+            let can_extend = ply < EXTENSION_PLY_FACTOR * ctx.root_depth && ply + depth < MAX_DEPTH;
+
+            // Singular Extensions: If the hash move is (by some margin) better than all the alternatives, searched to about half the depth,
+            // it's the only move here - extend it. Only for entries that are (likely) a lower bound on a good enough search.
+            let mut singular = false;
+            if ctx.options.singular
+                && can_extend
+                && ply > 0
+                && singular_move.is_none()
+                && depth >= SE_MIN_DEPTH
+                && let Some((hashed_depth, hashed)) = tt_entry
+                && hashed_depth + SE_DEPTH_MARGIN >= depth
+                && hashed.eval_type != EvalType::Upper
+                && !is_mate(hashed.value())
+            {
+                let singular_beta = hashed.value() - SE_MARGIN * depth as i32;
+                ctx.stats.singular += 1;
+                ctx.singular_move[ply] = tt_move;
+                let child = negamax(
+                    state,
+                    (depth - 1) / 2,
+                    ply,
+                    singular_beta - 1,
+                    singular_beta,
+                    ctx,
+                    tt,
+                );
+                ctx.singular_move[ply] = CompactMove::NONE;
+
+                if ctx.stopped() {
+                    return Variation::terminal(DRAW_VALUE);
+                }
+
+                if child.eval < singular_beta {
+                    ctx.stats.singular_ext += 1;
+                    singular = true;
+                }
+            }
+
+            let alpha_orig = alpha;
             let mut var = Variation::terminal(-INF_VALUE);
-            let mut eval_type: EvalType = EvalType::Exact;
 
-            for mv in &legal_moves {
-                state.make(mv);
-                var.max_assign(mv, negamax(state, depth - 1, -beta, -alpha, stats, tt));
-                state.unmake(mv, &irs);
+            let mut failed_quiets: Vec<Move> = Vec::new();
+            let mut num_legal = 0;
+            let mut num_moves = 0;
+
+            let mut picker = MovePicker::new(tt_move, ctx.killers[ply], ctx.countermove(ply), true);
+            while let Some(mv) = picker.next(state, &ctx.history[state.to_move as usize]) {
+                num_legal += 1;
+                if singular_move.matches(&mv) || ply == 0 && excluding && !ctx.is_root_move(&mv) {
+                    continue;
+                }
+                let index = num_moves;
+                num_moves += 1;
+
+                let tactical = state.is_tactical(&mv);
+                let killer = ctx.is_killer(ply, &mv);
+                ctx.make(state, &mv);
+                ctx.path[ply] = Some(mv);
+
+                let gives_check = state.num_checks > 0;
+
+                // Quiet moves are the ones we can afford to look at less closely
+                let quiet = !tactical && !killer && !in_check && !gives_check;
+
+                // Check Extensions: Forcing lines get searched a ply deeper, so they can resolve before the horizon
+                let extension = if can_extend && ctx.options.check_ext && gives_check {
+                    ctx.stats.check_ext += 1;
+                    1
+                } else if singular && tt_move.matches(&mv) {
+                    1
+                } else {
+                    0
+                };
+                let child_depth = depth - 1 + extension;
+
+                // Late Move Pruning: At shallow depths, skip quiet moves late in the list altogether
+                if ctx.options.lmp
+                    && quiet
+                    && ply > 0
+                    && depth <= LMP_MAX_DEPTH
+                    && index >= LMP_BASE_MOVES + depth * depth
+                {
+                    ctx.unmake(state, &mv, &irs);
+                    ctx.stats.lmp += 1;
+                    continue;
+                }
+
+                // Futility Pruning: Near the horizon, quiet moves can't make up for being this far below alpha
+                if ctx.options.futility
+                    && quiet
+                    && ply > 0
+                    && index > 0
+                    && depth <= FUTILITY_MAX_DEPTH
+                    && !is_mate(alpha)
+                    && static_eval + ctx.options.futility_margin * depth as i32 <= alpha
+                {
+                    ctx.unmake(state, &mv, &irs);
+                    ctx.stats.futility += 1;
+                    continue;
+                }
+
+                // Late Move Reductions: Search quiet moves late in the list to a reduced depth
+                let reduction =
+                    if ctx.options.lmr && quiet && depth >= LMR_MIN_DEPTH && index >= LMR_MIN_MOVES
+                    {
+                        let reduction = LMR_TABLE[cmp::min(depth, MAX_DEPTH)]
+                            [cmp::min(index, LMR_MAX_MOVES - 1)]
+                        .saturating_sub(pv_node as usize);
+                        cmp::min(reduction, depth - 2) // Don't drop into quiescence
+                    } else {
+                        0
+                    };
+
+                let child = if index == 0 || (!ctx.options.pvs && reduction == 0) {
+                    negamax(state, child_depth, ply + 1, -beta, -alpha, ctx, tt)
+                } else {
+                    // PVS: The first move is expected to be the best, so we only try to prove that the rest are worse, with a null window.
+                    // If that fails (the move is better than alpha, but not good enough for a beta cutoff), re-search with the full window.
+                    let (child_alpha, child_beta) = if ctx.options.pvs {
+                        ctx.stats.pvs_null_window += 1;
+                        (-alpha - 1, -alpha)
+                    } else {
+                        (-beta, -alpha)
+                    };
+
+                    let mut child = if reduction > 0 {
+                        // LMR: If the reduced search beats alpha, we can't trust it - re-search to the full depth
+                        ctx.stats.lmr += 1;
+                        let child = negamax(
+                            state,
+                            child_depth - reduction,
+                            ply + 1,
+                            child_alpha,
+                            child_beta,
+                            ctx,
+                            tt,
+                        );
+                        if alpha < -child.eval {
+                            ctx.stats.lmr_research += 1;
+                            negamax(
+                                state,
+                                child_depth,
+                                ply + 1,
+                                child_alpha,
+                                child_beta,
+                                ctx,
+                                tt,
+                            )
+                        } else {
+                            child
+                        }
+                    } else {
+                        negamax(
+                            state,
+                            child_depth,
+                            ply + 1,
+                            child_alpha,
+                            child_beta,
+                            ctx,
+                            tt,
+                        )
+                    };
+
+                    if ctx.options.pvs && alpha < -child.eval && -child.eval < beta {
+                        ctx.stats.pvs_research += 1;
+                        child = negamax(state, child_depth, ply + 1, -beta, -alpha, ctx, tt);
+                    }
+
+                    child
+                };
+                ctx.unmake(state, &mv, &irs);
+                var.max_assign(&mv, child);
+
+                // Aborted: the result is incomplete, don't store it
+                if ctx.stopped() {
+                    return var;
+                }
 
                 alpha = cmp::max(alpha, var.eval);
 
                 // Failing soft
                 if beta <= alpha {
-                    eval_type = EvalType::Alpha;
+                    if !tactical {
+                        ctx.update_quiet_stats(ply, depth, &mv, &failed_quiets);
+                    }
                     break;
                 }
+
+                if !tactical {
+                    failed_quiets.push(mv);
+                }
             }
 
-            tt.set(
-                state.hash,
-                depth,
-                Eval {
-                    eval_type,
-                    value: var.eval,
-                },
-            );
+            // No legal moves: it was an end node after all
+            if num_legal == 0 {
+                if singular_move.is_none() {
+                    ctx.stats.middle -= 1;
+                    ctx.stats.end += 1;
+                }
+                return Variation::terminal(if in_check { mated_in(ply) } else { DRAW_VALUE });
+            }
+
+            // Failing low, we don't know which move is best - keep the old hash move
+            let (eval_type, best_move) = if var.eval <= alpha_orig {
+                (EvalType::Upper, tt_move)
+            } else if beta <= var.eval {
+                (EvalType::Lower, var.move_list.front().unwrap().compact())
+            } else {
+                (EvalType::Exact, var.move_list.front().unwrap().compact())
+            };
+
+            if !excluding {
+                tt.set(
+                    state.hash,
+                    depth,
+                    Eval::new(eval_type, value_to_tt(var.eval, ply), best_move),
+                );
+            }
 
             var
         }
     } else {
-        stats.end += 1;
-        if status == Status::Checkmate {
-            Variation::terminal(-MATE_VALUE)
+        ctx.stats.end += 1;
+        Variation::terminal(DRAW_VALUE)
+    }
+}
+
+// Called with ( depth, variation, context, tt ) after every completed iteration
+pub type Report<'a, E = HeuristicEvaluator> =
+    dyn FnMut(usize, &Variation, &SearchContext<E>, &SharedHashTable<Eval>) + 'a;
+
+// Iterative Deepening with Aspiration Windows
+// Searches depth 1, 2, ... (till we hit one of the limits), calling report after every completed iteration.
+// Returns the Variation from the deepest completed iteration - an aborted iteration is discarded.
+// Lazy SMP helpers with an odd thread_id search one ply deeper at every iteration.
+pub fn iterative_deepening<E: Evaluator>(
+    state: &mut State,
+    ctx: &mut SearchContext<E>,
+    tt: &SharedHashTable<Eval>,
+    report: &mut Report<'_, E>,
+) -> Variation {
+    ctx.evaluator.init(state);
+
+    // In case even the first iteration is aborted: fall back to the best move as per move-ordering
+    let mut best = Variation::terminal(-INF_VALUE);
+    if let Some(mv) = state.node_info().0.iter().find(|mv| ctx.is_root_move(mv)) {
+        best.move_list.push_back(*mv);
+    }
+
+    let max_depth = ctx.limits.max_depth();
+    for depth in 1..=max_depth {
+        let depth = cmp::min(depth + ctx.thread_id % 2, max_depth);
+
+        // Search with a narrow window around the previous score, widen and re-search if we fall outside it
+        let mut delta = ASPIRATION_WINDOW;
+        let (mut alpha, mut beta) = if depth < ASPIRATION_MIN_DEPTH {
+            (-INF_VALUE, INF_VALUE)
         } else {
-            Variation::terminal(DRAW_VALUE)
+            (
+                cmp::max(best.eval - delta, -INF_VALUE),
+                cmp::min(best.eval + delta, INF_VALUE),
+            )
+        };
+
+        let var = loop {
+            let var = negamax(state, depth, 0, alpha, beta, ctx, tt);
+
+            if ctx.stopped() {
+                break None;
+            } else if var.eval <= alpha {
+                // Fail low
+                beta = (alpha + beta) / 2;
+                alpha = cmp::max(var.eval - delta, -INF_VALUE);
+            } else if beta <= var.eval {
+                // Fail high
+                beta = cmp::min(var.eval + delta, INF_VALUE);
+            } else {
+                break Some(var);
+            }
+
+            delta *= 2;
+        };
+
+        match var {
+            Some(var) => {
+                best = var;
+                report(depth, &best, ctx, tt);
+
+                if ctx.iteration_done(depth, &best) {
+                    break;
+                }
+            }
+            None => {
+                ctx.aborted();
+                break;
+            }
+        }
+    }
+
+    best
+}
+
+// Search entry point: Lazy SMP with num_threads threads, all searching the same position and sharing the TT.
+// The main thread (this one) enforces the limits and reports; once it's done, it stops the helpers.
+// Returns the deepest completed Variation amongst all the threads (the main thread's, on a tie).
+// The reason the search ended is left in ctx.stop_reason.
+pub fn lazy_smp<E: Evaluator>(
+    state: &mut State,
+    num_threads: usize,
+    ctx: &mut SearchContext<E>,
+    tt: &SharedHashTable<Eval>,
+    report: &mut Report<'_, E>,
+) -> Variation {
+    tt.new_search();
+    ctx.start = Instant::now();
+
+    if num_threads <= 1 {
+        return iterative_deepening(state, ctx, tt, report);
+    }
+
+    let helper_stop = Arc::new(AtomicBool::new(false));
+    thread::scope(|scope| {
+        let helpers: Vec<_> = (1..num_threads)
+            .map(|thread_id| {
+                let mut state = state.clone();
+                let mut ctx = ctx.helper(thread_id, helper_stop.clone());
+                scope.spawn(move || {
+                    let mut completed = 0;
+                    let var =
+                        iterative_deepening(&mut state, &mut ctx, tt, &mut |depth, _, _, _| {
+                            completed = depth
+                        });
+                    ctx.sync_nodes();
+                    (completed, var)
+                })
+            })
+            .collect();
+
+        let mut completed = 0;
+        let mut best = iterative_deepening(state, ctx, tt, &mut |depth, var, ctx, tt| {
+            completed = depth;
+            report(depth, var, ctx, tt);
+        });
+
+        helper_stop.store(true, Ordering::Relaxed);
+        for helper in helpers {
+            let (depth, var) = helper.join().expect("Search thread panicked!");
+            if depth > completed && !var.move_list.is_empty() {
+                completed = depth;
+                best = var;
+            }
+        }
+
+        ctx.sync_nodes();
+        best
+    })
+}
+
+// Called with ( depth, variations, context, tt ) after every completed iteration
+pub type MultiReport<'a, E = HeuristicEvaluator> =
+    dyn FnMut(usize, &[Variation], &SearchContext<E>, &SharedHashTable<Eval>) + 'a;
+
+// MultiPV: The best num_pv root moves, each with its exact score and PV, best first.
+// At every depth, searches the root with a full window, excludes the best move found, and searches again.
+// Single threaded, and without Aspiration Windows (we want exact scores for all the lines).
+// Returns the Variations from the deepest completed iteration - an aborted iteration is discarded.
+// The reason the search ended is left in ctx.stop_reason.
+pub fn multi_pv<E: Evaluator>(
+    state: &mut State,
+    num_pv: usize,
+    ctx: &mut SearchContext<E>,
+    tt: &SharedHashTable<Eval>,
+    report: &mut MultiReport<'_, E>,
+) -> Vec<Variation> {
+    tt.new_search();
+    ctx.start = Instant::now();
+    ctx.excluded.clear();
+    ctx.evaluator.init(state);
+
+    // In case even the first iteration is aborted: fall back to the best move as per move-ordering
+    let root_moves: Vec<Move> = state
+        .node_info()
+        .0
+        .into_iter()
+        .filter(|mv| ctx.is_root_move(mv))
+        .collect();
+    let mut best = Variation::terminal(-INF_VALUE);
+    if let Some(mv) = root_moves.first() {
+        best.move_list.push_back(*mv);
+    }
+    let mut lines = vec![best];
+    let num_pv = num_pv.clamp(1, cmp::max(root_moves.len(), 1));
+
+    for depth in 1..=ctx.limits.max_depth() {
+        let mut depth_lines: Vec<Variation> = Vec::new();
+
+        while depth_lines.len() < num_pv {
+            let var = negamax(state, depth, 0, -INF_VALUE, INF_VALUE, ctx, tt);
+            if ctx.stopped() {
+                break;
+            }
+
+            let game_over = var.move_list.is_empty();
+            if let Some(mv) = var.move_list.front() {
+                ctx.excluded.push(mv.compact());
+            }
+            depth_lines.push(var);
+
+            if game_over {
+                break;
+            }
+        }
+        ctx.excluded.clear();
+
+        if ctx.stopped() {
+            ctx.aborted();
+            break;
+        }
+
+        // Later lines might have turned out better (search instability) - keep them ranked
+        depth_lines.sort_by_key(|var| -var.eval);
+        lines = depth_lines;
+        report(depth, &lines, ctx, tt);
+
+        if ctx.iteration_done(depth, &lines[0]) {
+            break;
         }
     }
+
+    lines
 }
@@ -55,7 +55,7 @@ pub fn play() {
 
     let search_depth: usize = 4;
     let mut state = State::new();
-    let mut tt: HashTable<Eval> = HashTable::new(24);
+    let tt: SharedHashTable<Eval> = SharedHashTable::new(24);
 
     loop {
         if state.to_move == opponent_color {
@@ -78,22 +78,21 @@ pub fn play() {
                 }
             }
         } else {
-            let mut stats = SearchStats::new();
-            let pv = negamax(
-                &mut state,
-                search_depth,
-                -MATE_VALUE,
-                MATE_VALUE,
-                &mut stats,
-                &mut tt,
-            );
+            let mut ctx = SearchContext::new();
+            ctx.limits.depth = Some(search_depth);
+            let pv = lazy_smp(&mut state, 1, &mut ctx, &tt, &mut |_, _, _, _| {});
             let mv = pv.move_list.front().unwrap();
             state.make(mv);
             println!("I just played: {}", mv);
-            println!(
-                "My evaluation is {}, at a depth of {}.\n",
-                pv.eval, search_depth
-            );
+            match Score::new(pv.eval) {
+                Score::Cp(cp) => {
+                    println!("My evaluation is {}, at a depth of {}.\n", cp, search_depth)
+                }
+                Score::Mate(moves) if moves > 0 => {
+                    println!("I see a mate in {} moves.\n", moves)
+                }
+                Score::Mate(moves) => println!("I am getting mated in {} moves.\n", -moves),
+            }
         }
     }
 }
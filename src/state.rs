@@ -10,6 +10,7 @@ use std::cmp::Ordering;
 use std::collections::VecDeque;
 use std::fmt;
 use std::ops::{Index, IndexMut};
+use std::sync::Arc;
 
 // A mailbox style board that encodes the contents of each square in u8
 pub type SimpleBoard = [u8; 64];
@@ -18,6 +19,7 @@ pub type SimpleBoard = [u8; 64];
 // Have to make it a Tuple Struct because Rust doesn't allow me to implement traits I don't own ( Index, IndexMut ), on types I don't own ( [T; N] )
 // Credit to "Crabby" for this hack
 // FIXME: Can we do something better here?
+#[derive(Copy, Clone)]
 pub struct BitBoard(pub [u64; 14]);
 
 impl Index<u8> for BitBoard {
@@ -96,10 +98,10 @@ impl Move {
     }
 
     pub fn is_promotion(&self) -> bool {
-        match (self.piece, self.from / 8) {
-            (WHITE_PAWN, 6) | (BLACK_PAWN, 1) => true,
-            _ => false,
-        }
+        matches!(
+            (self.piece, self.from / 8),
+            (WHITE_PAWN, 6) | (BLACK_PAWN, 1)
+        )
     }
 
     // A bb of what changed - ignoring en_passant
@@ -124,6 +126,44 @@ impl Move {
     pub fn score(&self) -> i32 {
         self.pst_eval.eval(self.piece & COLOR) + self.see
     }
+
+    pub fn compact(&self) -> CompactMove {
+        let promotion = if self.promotion == EMPTY {
+            0
+        } else {
+            (self.promotion & COLOR_MASK) >> 1
+        };
+
+        CompactMove((self.from | (self.to << 6) | ((promotion as usize) << 12)) as u16)
+    }
+
+    // Long Algebraic Notation, as used by UCI and CECP: e2e4, e7e8q, e1g1 (castling)
+    pub fn long_algebraic(&self) -> String {
+        let mut output = offset_to_algebraic(self.from);
+        output.push_str(&offset_to_algebraic(self.to));
+        if self.promotion != EMPTY {
+            output.push(piece_to_char(self.promotion | BLACK));
+        }
+
+        output
+    }
+}
+
+// CompactMove: from (6 bits) | to (6 bits) | promotion piece type (3 bits)
+// Identifies a move uniquely in a given position; from == to == 0 can't be a move, so that's the "no move"
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompactMove(pub u16);
+
+impl CompactMove {
+    pub const NONE: CompactMove = CompactMove(0);
+
+    pub fn is_none(&self) -> bool {
+        *self == CompactMove::NONE
+    }
+
+    pub fn matches(&self, mv: &Move) -> bool {
+        *self == mv.compact()
+    }
 }
 
 impl Ord for Move {
@@ -191,12 +231,17 @@ impl PSTEval {
         }
     }
 
+    // From MG_PHASE (middle-game) down to 0 (end-game)
+    #[inline]
+    pub fn phase(&self) -> i32 {
+        ((self.npm.clamp(EG_NPM_LIMIT, MG_NPM_LIMIT) - EG_NPM_LIMIT) * MG_PHASE)
+            / (MG_NPM_LIMIT - EG_NPM_LIMIT)
+    }
+
     #[inline]
     pub fn eval(&self, to_move: u8) -> i32 {
         // Tapered Eval from side-to-move's POV
-        let phase = ((cmp::max(EG_NPM_LIMIT, cmp::min(MG_NPM_LIMIT, self.npm)) - EG_NPM_LIMIT)
-            * MG_PHASE)
-            / (MG_NPM_LIMIT - EG_NPM_LIMIT);
+        let phase = self.phase();
         let eval = (phase * self.eval_mg + (MG_PHASE - phase) * self.eval_eg) / MG_PHASE;
         TEMPO_BONUS + if to_move == WHITE { eval } else { -eval }
     }
@@ -236,12 +281,15 @@ pub struct IRState {
 
     // Hash
     pub hash: u64,
+    pub pawn_hash: u64, // Pawns only - keys the pawn hash table
 
     // PSTEval
     pub pst_eval: PSTEval,
 }
 
 // Full State
+// Cheap to clone (e.g. one per search thread): the move and hash generators are shared
+#[derive(Clone)]
 pub struct State {
     // State (the board) + IRState
     pub simple_board: SimpleBoard,
@@ -253,7 +301,7 @@ pub struct State {
     pub fullmove_count: usize, // Starts at 1 (First Move)
 
     // Move Generator
-    pub mg: MoveGen,
+    pub mg: Arc<MoveGen>,
 
     // Control
     pub attacked: u64,
@@ -265,10 +313,11 @@ pub struct State {
     pub ep_possible: bool,
 
     // Hash Generator
-    pub hg: HashGen,
+    pub hg: Arc<HashGen>,
 
     // Hash
     pub hash: u64,
+    pub pawn_hash: u64, // Pawns only - keys the pawn hash table
 
     // History
     pub history: VecDeque<u64>,
@@ -343,15 +392,30 @@ impl fmt::Display for State {
     }
 }
 
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl State {
     pub fn generate_state_from_fen(fen: &str) -> Self {
+        match Self::try_from_fen(fen) {
+            Ok(state) => state,
+            Err(error) => panic!("{}:\n{}", error, fen),
+        }
+    }
+
+    // Parses and validates a FEN - for FENs that come from the outside world (GUIs), where panicking is not an option
+    pub fn try_from_fen(fen: &str) -> Result<Self, String> {
         // Check that fen specifies the required number of fields
         let num_fields = fen.split_whitespace().count();
-        assert!(
-            num_fields == 5 || num_fields == 6,
-            "FEN must specify exactly 5 or 6 fields ( if fullmove_count is also included ):\n{}",
-            fen
-        );
+        if num_fields != 5 && num_fields != 6 {
+            return Err(
+                "FEN must specify exactly 5 or 6 fields ( if fullmove_count is also included )"
+                    .to_string(),
+            );
+        }
 
         let iter = fen.split_whitespace().enumerate();
         let mut state = State {
@@ -362,7 +426,7 @@ impl State {
             en_passant: ERR_POS,
             halfmove_clock: 0,
             fullmove_count: 0,
-            mg: MoveGen::new(true),
+            mg: Arc::new(MoveGen::new(true)),
             attacked: 0,
             num_checks: 0,
             check_blocker: FULL_BOARD,
@@ -370,8 +434,9 @@ impl State {
             a_pins: [FULL_BOARD; 64],
             control: [0; 64],
             ep_possible: false,
-            hg: HashGen::new(),
+            hg: Arc::new(HashGen::new()),
             hash: 0,
+            pawn_hash: 0,
             history: VecDeque::new(),
             pst_eval: PSTEval::new(),
         };
@@ -380,31 +445,34 @@ impl State {
             match section_number {
                 0 => {
                     // Populate simple_board
-                    assert!(
-                        section.rsplit('/').count() == 8,
-                        "Position should contain 8 rows:\n{}",
-                        section
-                    );
+                    if section.rsplit('/').count() != 8 {
+                        return Err(format!("Position should contain 8 rows: {}", section));
+                    }
                     let row_iter = section.rsplit('/').enumerate();
                     let mut position_offset: usize = 0;
 
                     for (row_number, row) in row_iter {
-                        assert!(
-                            position_offset == 8 * row_number,
-                            "Invalid position_offset: {}, at row_number: {}",
-                            position_offset,
-                            row_number
-                        );
-                        let char_iter = row.chars();
-
-                        for c in char_iter {
+                        for c in row.chars() {
                             if let Some(empty_space) = c.to_digit(10) {
                                 position_offset += empty_space as usize;
-                            } else {
-                                state.simple_board[position_offset] = char_to_piece(c);
+                            } else if position_offset < 8 * (row_number + 1) {
+                                state.simple_board[position_offset] = match c {
+                                    'P' | 'N' | 'B' | 'R' | 'Q' | 'K' | 'p' | 'n' | 'b' | 'r'
+                                    | 'q' | 'k' => char_to_piece(c),
+                                    _ => return Err(format!("Invalid piece: {}", c)),
+                                };
                                 position_offset += 1;
+                            } else {
+                                return Err(format!(
+                                    "Rank {} is not 8 squares long",
+                                    row_number + 1
+                                ));
                             }
                         }
+
+                        if position_offset != 8 * (row_number + 1) {
+                            return Err(format!("Rank {} is not 8 squares long", row_number + 1));
+                        }
                     }
 
                     // Populate bit_board
@@ -415,7 +483,7 @@ impl State {
                     state.to_move = match section {
                         "w" => WHITE,
                         "b" => BLACK,
-                        _ => panic!("to_move is invalid: {}", section),
+                        _ => return Err(format!("to_move is invalid: {}", section)),
                     }
                 }
                 2 => {
@@ -430,7 +498,7 @@ impl State {
                                     'Q' => WQ_CASTLE,
                                     'k' => BK_CASTLE,
                                     'q' => BQ_CASTLE,
-                                    _ => panic!("Invalid castling token: {}", c),
+                                    _ => return Err(format!("Invalid castling token: {}", c)),
                                 }
                             }
                         }
@@ -438,94 +506,105 @@ impl State {
                 }
                 3 => {
                     // en_passant
-                    state.en_passant = match section {
-                        "-" => ERR_POS,
-                        _ => algebraic_to_offset(section),
+                    state.en_passant = match section.as_bytes() {
+                        b"-" => ERR_POS,
+                        [b'a'..=b'h', b'1'..=b'8'] => algebraic_to_offset(section),
+                        _ => return Err(format!("Invalid en_passant square: {}", section)),
                     }
                 }
                 4 => {
                     // halfmove_clock
                     state.halfmove_clock = match section {
                         "-" => 0,
-                        _ => section.parse::<usize>().unwrap(),
+                        _ => section
+                            .parse::<usize>()
+                            .map_err(|_| format!("Invalid halfmove_clock: {}", section))?,
                     }
                 }
                 5 => {
                     // fullmove_count
                     state.fullmove_count = match section {
                         "-" => 1,
-                        _ => section.parse::<usize>().unwrap(),
+                        _ => section
+                            .parse::<usize>()
+                            .map_err(|_| format!("Invalid fullmove_count: {}", section))?,
                     }
                 }
                 _ => {}
             }
         }
 
+        state.validate()?;
         state.compute_control();
-        state.state_check();
         state.set_hash();
         state.set_pst_eval();
 
-        state
+        Ok(state)
     }
 
     pub fn state_check(&self) {
-        // Very basic checks -> does not assert that the position is a child of START_FEN (can be reached from the initial position)
-        // Sufficient for the engine to play
+        if let Err(error) = self.validate() {
+            panic!("{}", error);
+        }
+    }
 
+    // Very basic checks -> does not check that the position is a child of START_FEN (can be reached from the initial position)
+    // Sufficient for the engine to play. Only needs the boards to be set up.
+    pub fn validate(&self) -> Result<(), String> {
         // Check number of kings
-        assert_eq!(self.bit_board[WHITE_KING].count_ones(), 1);
-        assert_eq!(self.bit_board[BLACK_KING].count_ones(), 1);
-
-        // Check that opposing king is not in check if it's our move
-        assert_eq!(
-            self.defended & self.bit_board[(self.to_move ^ COLOR) | KING],
-            0
-        );
-
-        // castling
-        if self.castling & W_CASTLE != 0 {
-            assert_eq!(self.simple_board[WK_START], WHITE_KING);
-
-            if self.castling & WK_CASTLE != 0 {
-                assert_eq!(self.simple_board[WKR_START], WHITE_ROOK);
-            }
-
-            if self.castling & WQ_CASTLE != 0 {
-                assert_eq!(self.simple_board[WQR_START], WHITE_ROOK);
-            }
+        if self.bit_board[WHITE_KING].count_ones() != 1
+            || self.bit_board[BLACK_KING].count_ones() != 1
+        {
+            return Err("Each side must have exactly one king".to_string());
         }
 
-        if self.castling & B_CASTLE != 0 {
-            assert_eq!(self.simple_board[BK_START], BLACK_KING);
+        // No pawns on the first or last rank
+        if (self.bit_board[WHITE_PAWN] | self.bit_board[BLACK_PAWN])
+            & (FIRST_RANK | FIRST_RANK << 56)
+            != 0
+        {
+            return Err("Pawns can't be on the first or last rank".to_string());
+        }
 
-            if self.castling & BK_CASTLE != 0 {
-                assert_eq!(self.simple_board[BKR_START], BLACK_ROOK);
-            }
+        // Check that opposing king is not in check if it's our move
+        let opp_king_pos = self.bit_board[(self.to_move ^ COLOR) | KING].trailing_zeros() as usize;
+        let occupancy = self.bit_board[WHITE_ALL] | self.bit_board[BLACK_ALL];
+        if self.attackers(opp_king_pos, occupancy) & self.bit_board[self.to_move | ALL] != 0 {
+            return Err("The side not to move is in check".to_string());
+        }
 
-            if self.castling & BQ_CASTLE != 0 {
-                assert_eq!(self.simple_board[BQR_START], BLACK_ROOK);
+        // castling
+        let castling_rooks = [
+            (WK_CASTLE, WK_START, WHITE_KING, WKR_START, WHITE_ROOK),
+            (WQ_CASTLE, WK_START, WHITE_KING, WQR_START, WHITE_ROOK),
+            (BK_CASTLE, BK_START, BLACK_KING, BKR_START, BLACK_ROOK),
+            (BQ_CASTLE, BK_START, BLACK_KING, BQR_START, BLACK_ROOK),
+        ];
+        for (right, king_pos, king, rook_pos, rook) in castling_rooks {
+            if self.castling & right != 0
+                && (self.simple_board[king_pos] != king || self.simple_board[rook_pos] != rook)
+            {
+                return Err("Castling rights don't match the position".to_string());
             }
         }
 
         // en_passant
         if self.en_passant != ERR_POS {
-            match self.to_move {
-                WHITE => {
-                    assert_eq!(self.en_passant / 8, 5);
-                    assert_eq!(self.simple_board[self.en_passant - 8], BLACK_PAWN);
-                    assert_eq!(self.simple_board[self.en_passant], EMPTY);
-                    assert_eq!(self.simple_board[self.en_passant + 8], EMPTY);
-                }
-                BLACK => {
-                    assert_eq!(self.en_passant / 8, 2);
-                    assert_eq!(self.simple_board[self.en_passant + 8], WHITE_PAWN);
-                    assert_eq!(self.simple_board[self.en_passant], EMPTY);
-                    assert_eq!(self.simple_board[self.en_passant - 8], EMPTY);
-                }
-                _ => {}
+            let (ep_rank, pawn, forward): (usize, u8, isize) = match self.to_move {
+                WHITE => (5, BLACK_PAWN, 8),
+                _ => (2, WHITE_PAWN, -8),
+            };
+            let ep = self.en_passant as isize;
+            if self.en_passant / 8 != ep_rank
+                || self.simple_board[(ep - forward) as usize] != pawn
+                || self.simple_board[self.en_passant] != EMPTY
+                || self.simple_board[(ep + forward) as usize] != EMPTY
+            {
+                return Err("en_passant square doesn't match the position".to_string());
             }
         }
+
+        Ok(())
     }
 
     pub fn fen(&self, strict_ep: bool) -> String {
@@ -616,6 +695,7 @@ impl State {
         self.control = irs.control;
         self.ep_possible = irs.ep_possible;
         self.hash = irs.hash;
+        self.pawn_hash = irs.pawn_hash;
         self.pst_eval = irs.pst_eval;
     }
 
@@ -632,6 +712,7 @@ impl State {
             control: self.control,
             ep_possible: self.ep_possible,
             hash: self.hash,
+            pawn_hash: self.pawn_hash,
             pst_eval: self.pst_eval,
         }
     }
@@ -660,6 +741,13 @@ impl State {
             self.bit_board[mv.capture] ^= 1 << mv.to;
             self.hash ^= self.hg.piece(mv.capture, mv.to); // HASH_UPDATE
         }
+        if mv.piece & COLOR_MASK == PAWN {
+            self.pawn_hash ^= self.hg.piece(mv.piece, mv.from); // HASH_UPDATE
+            self.pawn_hash ^= self.hg.piece(mv.piece, mv.to); // HASH_UPDATE
+        }
+        if mv.capture & COLOR_MASK == PAWN {
+            self.pawn_hash ^= self.hg.piece(mv.capture, mv.to); // HASH_UPDATE
+        }
 
         // Update castling state and en_passant; handle promotion
         // Update simple_board and bit_board for Rook if castling
@@ -672,6 +760,7 @@ impl State {
                     self.bit_board[mv.promotion] ^= 1 << mv.to;
                     self.hash ^= self.hg.piece(WHITE_PAWN, mv.to); // HASH_UPDATE
                     self.hash ^= self.hg.piece(mv.promotion, mv.to); // HASH_UPDATE
+                    self.pawn_hash ^= self.hg.piece(WHITE_PAWN, mv.to); // HASH_UPDATE
                 } else {
                     match mv.to - mv.from {
                         16 => {
@@ -683,6 +772,7 @@ impl State {
                                 self.simple_board[ep_target] = EMPTY;
                                 self.bit_board[BLACK_PAWN] ^= 1 << ep_target;
                                 self.hash ^= self.hg.piece(BLACK_PAWN, ep_target);
+                                self.pawn_hash ^= self.hg.piece(BLACK_PAWN, ep_target);
                                 // HASH_UPDATE
                             }
                         }
@@ -722,6 +812,7 @@ impl State {
                     self.bit_board[mv.promotion] ^= 1 << mv.to;
                     self.hash ^= self.hg.piece(BLACK_PAWN, mv.to); // HASH_UPDATE
                     self.hash ^= self.hg.piece(mv.promotion, mv.to); // HASH_UPDATE
+                    self.pawn_hash ^= self.hg.piece(BLACK_PAWN, mv.to); // HASH_UPDATE
                 } else {
                     match mv.from - mv.to {
                         16 => {
@@ -733,6 +824,7 @@ impl State {
                                 self.simple_board[ep_target] = EMPTY;
                                 self.bit_board[WHITE_PAWN] ^= 1 << ep_target;
                                 self.hash ^= self.hg.piece(WHITE_PAWN, ep_target);
+                                self.pawn_hash ^= self.hg.piece(WHITE_PAWN, ep_target);
                                 // HASH_UPDATE
                             }
                         }
@@ -879,6 +971,43 @@ impl State {
         } // update fullmove_count
     }
 
+    // Pass the turn to the opponent (used by null-move pruning); never call this when in check
+    pub fn make_null(&mut self) {
+        // Add current hash to history
+        self.history.push_front(self.hash);
+
+        let side = self.to_move;
+        self.hash ^= self.hg.side_hash; // HASH_UPDATE
+
+        // Remove old ep from hash
+        if self.ep_possible {
+            self.hash ^= self.hg.ep(self.en_passant); // HASH_UPDATE
+        }
+
+        self.en_passant = ERR_POS; // clear en_passant
+        self.to_move ^= COLOR; // set side
+        if side == BLACK {
+            self.fullmove_count += 1;
+        } // update fullmove_count
+        self.halfmove_clock += 1;
+
+        self.compute_control();
+    }
+
+    pub fn unmake_null(&mut self, irs: &IRState) {
+        // Pop the current hash
+        self.history.pop_front();
+
+        let side = self.to_move ^ COLOR; // side that just passed
+
+        self.set_ir_state(irs);
+
+        self.to_move ^= COLOR; // set side
+        if side == BLACK {
+            self.fullmove_count -= 1;
+        } // update fullmove_count
+    }
+
     pub fn ep_flag(&self) -> bool {
         self.en_passant != ERR_POS
     }
@@ -947,10 +1076,42 @@ impl State {
 
     // All pseudo-legal moves
     pub fn moves(&self) -> Vec<Move> {
+        let mut moves: Vec<Move> = Vec::new();
+        self.add_moves(FULL_BOARD, FULL_BOARD, &mut moves);
+        moves
+    }
+
+    // Pseudo-legal tactical moves: captures (en passant included) and promotions, as per is_tactical
+    pub fn tactical_pl_moves(&self, moves: &mut Vec<Move>) {
+        let opp_bb = self.bit_board[(self.to_move ^ COLOR) | ALL];
+        self.add_moves(opp_bb, opp_bb | self.ep_bb() | self.promotion_rank(), moves);
+    }
+
+    // Pseudo-legal quiet moves: everything tactical_pl_moves leaves out
+    pub fn quiet_pl_moves(&self, moves: &mut Vec<Move>) {
+        let opp_bb = self.bit_board[(self.to_move ^ COLOR) | ALL];
+        self.add_moves(
+            !opp_bb,
+            !(opp_bb | self.ep_bb() | self.promotion_rank()),
+            moves,
+        );
+    }
+
+    #[inline]
+    pub fn promotion_rank(&self) -> u64 {
+        if self.to_move == WHITE {
+            FIRST_RANK << 56
+        } else {
+            FIRST_RANK
+        }
+    }
+
+    // Pseudo-legal moves to the targets (pawn_targets for pawns), added to moves
+    pub fn add_moves(&self, targets: u64, pawn_targets: u64, moves: &mut Vec<Move>) {
         let side = self.to_move;
         let opp_side = side ^ COLOR;
 
-        let not_friendly = !self.bit_board[side | ALL];
+        let not_friendly = !self.bit_board[side | ALL] & targets;
         let occupancy = self.bit_board[side | ALL] | self.bit_board[opp_side | ALL];
         let occupancy_w_ep = occupancy | self.ep_bb();
 
@@ -958,15 +1119,16 @@ impl State {
         let mut bb: u64;
         let mut moves_bb: u64;
         let mut pos: usize;
-        let mut moves: Vec<Move> = Vec::new();
 
         // PAWN
         piece = side | PAWN;
         bb = self.bit_board[piece];
         while bb != 0 {
             pos = pop_lsb_pos(&mut bb);
-            moves_bb = self.mg.p_moves(pos, side, occupancy_w_ep) & not_friendly;
-            self.add_moves_from_bb(piece, pos, &mut moves_bb, &mut moves);
+            moves_bb = self.mg.p_moves(pos, side, occupancy_w_ep)
+                & !self.bit_board[side | ALL]
+                & pawn_targets;
+            self.add_moves_from_bb(piece, pos, &mut moves_bb, moves);
         }
 
         // KNIGHT
@@ -975,7 +1137,7 @@ impl State {
         while bb != 0 {
             pos = pop_lsb_pos(&mut bb);
             moves_bb = self.mg.n_moves(pos) & not_friendly;
-            self.add_moves_from_bb(piece, pos, &mut moves_bb, &mut moves);
+            self.add_moves_from_bb(piece, pos, &mut moves_bb, moves);
         }
 
         // BISHOP
@@ -984,7 +1146,7 @@ impl State {
         while bb != 0 {
             pos = pop_lsb_pos(&mut bb);
             moves_bb = self.mg.b_moves(pos, occupancy) & not_friendly;
-            self.add_moves_from_bb(piece, pos, &mut moves_bb, &mut moves);
+            self.add_moves_from_bb(piece, pos, &mut moves_bb, moves);
         }
 
         // ROOK
@@ -993,7 +1155,7 @@ impl State {
         while bb != 0 {
             pos = pop_lsb_pos(&mut bb);
             moves_bb = self.mg.r_moves(pos, occupancy) & not_friendly;
-            self.add_moves_from_bb(piece, pos, &mut moves_bb, &mut moves);
+            self.add_moves_from_bb(piece, pos, &mut moves_bb, moves);
         }
 
         // QUEEN
@@ -1002,7 +1164,7 @@ impl State {
         while bb != 0 {
             pos = pop_lsb_pos(&mut bb);
             moves_bb = self.mg.q_moves(pos, occupancy) & not_friendly;
-            self.add_moves_from_bb(piece, pos, &mut moves_bb, &mut moves);
+            self.add_moves_from_bb(piece, pos, &mut moves_bb, moves);
         }
 
         // KING
@@ -1011,10 +1173,8 @@ impl State {
         while bb != 0 {
             pos = pop_lsb_pos(&mut bb);
             moves_bb = self.mg.k_moves(pos, side, occupancy, self.castling) & not_friendly;
-            self.add_moves_from_bb(piece, pos, &mut moves_bb, &mut moves);
+            self.add_moves_from_bb(piece, pos, &mut moves_bb, moves);
         }
-
-        moves
     }
 
     pub fn compute_control(&mut self) {
@@ -1236,7 +1396,7 @@ impl State {
             pinners = self
                 .mg
                 .r_moves(opp_king_pos, occupancy_wo_opp_king ^ possibly_pinned)
-                & (self.bit_board[opp_side | QUEEN] | self.bit_board[opp_side | ROOK]);
+                & (self.bit_board[side | QUEEN] | self.bit_board[side | ROOK]);
             while pinners != 0 {
                 pos = pop_lsb_pos(&mut pinners);
                 pin = line_segment(pos, opp_king_pos) ^ opp_king;
@@ -1387,6 +1547,16 @@ impl State {
         self.is_legal(mv)
     }
 
+    // Does the given side have anything other than Pawns (and the King)?
+    #[inline]
+    pub fn has_non_pawn_material(&self, side: u8) -> bool {
+        (self.bit_board[side | KNIGHT]
+            | self.bit_board[side | BISHOP]
+            | self.bit_board[side | ROOK]
+            | self.bit_board[side | QUEEN])
+            != 0
+    }
+
     #[inline]
     pub fn num_repetitions(&self, depth: usize) -> usize {
         self.history
@@ -1397,6 +1567,38 @@ impl State {
             .count()
     }
 
+    // Repetition Draw, as seen by the search (node_info has the strict rules, for adjudication)
+    // A single repetition of a position in the search path (the last ply plies) is already a draw - we could just repeat again.
+    // A position from the game history (before the root) has to have been repeated twice.
+    // Only the last window plies are looked at, e.g. to not look past a null move.
+    pub fn is_search_repetition(&self, ply: usize, window: usize) -> bool {
+        let rev_history = cmp::min(cmp::min(self.halfmove_clock, self.history.len()), window); // Available reversible history
+        let mut count = 0;
+
+        // history[ i ] is the position ( i + 1 ) plies back, the ones with the same side to move are at odd i
+        for (index, hash) in self
+            .history
+            .iter()
+            .take(rev_history)
+            .enumerate()
+            .skip(1)
+            .step_by(2)
+        {
+            if *hash == self.hash {
+                if index < ply {
+                    return true;
+                }
+
+                count += 1;
+                if count > 1 {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
     // This function returns legal Moves (sorted by PSTEval + SEE) and Game Status
     pub fn node_info(&self) -> (Vec<Move>, Status) {
         let mut moves = self.moves();
@@ -1417,39 +1619,78 @@ impl State {
         legal_moves.sort();
 
         // compute status and return
-        if legal_moves.is_empty() {
+        let status = if legal_moves.is_empty() {
             if self.num_checks > 0 {
-                (legal_moves, Status::Checkmate)
+                Status::Checkmate
             } else {
-                (legal_moves, Status::Stalemate)
+                Status::Stalemate
             }
-        } else if self.halfmove_clock > 99 {
-            (legal_moves, Status::FiftyMoveDraw)
         } else {
-            let rev_history = cmp::min(self.halfmove_clock, self.history.len()); // Available reversible history
-            if rev_history > 7 && self.num_repetitions(rev_history) > 1 {
-                (legal_moves, Status::RepetitionDraw)
-            } else {
-                // p_n_p = pieces and pawns
-                let p_n_p = (self.bit_board[WHITE_KING] | self.bit_board[BLACK_KING])
-                    ^ (self.bit_board[WHITE_ALL] | self.bit_board[BLACK_ALL]);
-                match p_n_p.count_ones() {
-                    0 => (legal_moves, Status::InsufficientMaterial),
-                    1 => {
-                        let survivor =
-                            self.simple_board[p_n_p.trailing_zeros() as usize] & COLOR_MASK;
-                        if survivor == BISHOP || survivor == KNIGHT {
-                            (legal_moves, Status::InsufficientMaterial)
-                        } else {
-                            (legal_moves, Status::Ongoing)
-                        }
+            self.draw_status()
+        };
+
+        (legal_moves, status)
+    }
+
+    // Draws that can be decided without the legal moves - Ongoing, if none apply.
+    // Checkmate still trumps the fifty move rule, so that's the one case we do need a legal move for.
+    pub fn draw_status(&self) -> Status {
+        if self.halfmove_clock > 99 && (self.num_checks == 0 || self.has_legal_move()) {
+            return Status::FiftyMoveDraw;
+        }
+
+        let rev_history = cmp::min(self.halfmove_clock, self.history.len()); // Available reversible history
+        if rev_history > 7 && self.num_repetitions(rev_history) > 1 {
+            Status::RepetitionDraw
+        } else {
+            // p_n_p = pieces and pawns
+            let p_n_p = (self.bit_board[WHITE_KING] | self.bit_board[BLACK_KING])
+                ^ (self.bit_board[WHITE_ALL] | self.bit_board[BLACK_ALL]);
+            match p_n_p.count_ones() {
+                0 => Status::InsufficientMaterial,
+                1 => {
+                    let survivor = self.simple_board[p_n_p.trailing_zeros() as usize] & COLOR_MASK;
+                    if survivor == BISHOP || survivor == KNIGHT {
+                        Status::InsufficientMaterial
+                    } else {
+                        Status::Ongoing
                     }
-                    _ => (legal_moves, Status::Ongoing),
                 }
+                _ => Status::Ongoing,
             }
         }
     }
 
+    pub fn has_legal_move(&self) -> bool {
+        self.moves().iter().any(|mv| self.is_legal(mv))
+    }
+
+    // The legal move a CompactMove (e.g. a hash move, which might be from another position) stands for, if any
+    pub fn compact_to_move(&self, cmv: CompactMove) -> Option<Move> {
+        if cmv.is_none() {
+            return None;
+        }
+
+        let from = (cmv.0 & 0x3F) as usize;
+        let to = ((cmv.0 >> 6) & 0x3F) as usize;
+        let promotion = ((cmv.0 >> 12) as u8) << 1;
+
+        let mut mv = Move::null_move(self.simple_board[from], from);
+        mv.to = to;
+        mv.capture = self.simple_board[to];
+        if promotion != 0 {
+            mv.promotion = self.to_move | promotion;
+        }
+
+        // Promotions have to say what they promote to, and nothing else can
+        if mv.piece == EMPTY || mv.is_promotion() != (promotion != 0) || !self.is_legal_strict(&mv)
+        {
+            None
+        } else {
+            Some(mv)
+        }
+    }
+
     // Legal Moves, unsorted
     pub fn legal_moves(&self) -> Vec<Move> {
         let mut moves = self.moves();
@@ -1502,6 +1743,7 @@ impl State {
 
     pub fn set_hash(&mut self) {
         self.hash = 0;
+        self.pawn_hash = 0;
 
         // to_move
         if self.to_move == WHITE {
@@ -1512,6 +1754,9 @@ impl State {
         for (pos, piece) in self.simple_board.iter().enumerate() {
             if *piece != EMPTY {
                 self.hash ^= self.hg.piece(*piece, pos);
+                if *piece & COLOR_MASK == PAWN {
+                    self.pawn_hash ^= self.hg.piece(*piece, pos);
+                }
             }
         }
 
@@ -1524,12 +1769,12 @@ impl State {
         }
     }
 
-    // Asserts that Incrementally computed hash is same as the one computed from scratch
+    // Asserts that Incrementally computed hashes are same as the ones computed from scratch
     // true = OK
     pub fn check_hash(&mut self) -> bool {
-        let hash = self.hash;
+        let (hash, pawn_hash) = (self.hash, self.pawn_hash);
         self.set_hash();
-        hash == self.hash
+        hash == self.hash && pawn_hash == self.pawn_hash
     }
 
     // Recursively check_hash till the given depth
@@ -1554,6 +1799,32 @@ impl State {
         }
     }
 
+    // Recursively check that make_null/unmake_null keep the hash consistent and restore the State
+    pub fn check_null_move_rec(&mut self, depth: usize) -> bool {
+        assert!(depth > 0, "Depth has to be greater than zero!");
+
+        let mut ok: bool = true;
+        let irs = self.ir_state();
+
+        if self.num_checks == 0 {
+            let fen = self.fen(false);
+            self.make_null();
+            ok = self.check_hash();
+            self.unmake_null(&irs);
+            ok = ok && fen == self.fen(false) && irs.hash == self.hash;
+        }
+
+        if depth > 1 {
+            for mv in &self.legal_moves() {
+                self.make(mv);
+                ok = ok && self.check_null_move_rec(depth - 1);
+                self.unmake(mv, &irs);
+            }
+        }
+
+        ok
+    }
+
     pub fn hash_perft(&mut self, depth: usize, divide: bool) -> u64 {
         // Initialize HashTable
         let num_bits = if depth < 7 {
@@ -1619,8 +1890,11 @@ impl State {
         }
     }
 
+    // Before the move is made: an en passant capture has no captured piece on its to square
     pub fn is_tactical(&self, mv: &Move) -> bool {
-        mv.capture != EMPTY || mv.promotion != EMPTY
+        mv.capture != EMPTY
+            || mv.promotion != EMPTY
+            || mv.to == self.en_passant && mv.piece & COLOR_MASK == PAWN
     }
 
     pub fn tactical_moves(&self, legal_moves: &[Move]) -> Vec<Move> {
@@ -1776,6 +2050,11 @@ impl State {
 
     // Calculate PSTEval from scratch and set
     pub fn set_pst_eval(&mut self) {
+        self.pst_eval = self.compute_pst_eval();
+    }
+
+    // Calculate PSTEval from scratch
+    pub fn compute_pst_eval(&self) -> PSTEval {
         let pawn_pst: &[i32] = &PAWN_PST;
         let knight_pst: &[i32] = &KNIGHT_PST;
         let bishop_pst: &[i32] = &BISHOP_PST;
@@ -1848,9 +2127,11 @@ impl State {
         eval_mg -= KING_MG_PST[pos];
         eval_eg -= KING_EG_PST[pos];
 
-        self.pst_eval.npm = npm;
-        self.pst_eval.eval_mg = eval_mg;
-        self.pst_eval.eval_eg = eval_eg;
+        PSTEval {
+            npm,
+            eval_mg,
+            eval_eg,
+        }
     }
 
     // Tapered Evaluation using PST
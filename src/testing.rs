@@ -1,9 +1,22 @@
+use crate::consts::*;
+use crate::evaluation::*;
+use crate::hashtables::*;
+use crate::mate_solver::*;
+use crate::movepick::*;
+use crate::pawns::*;
 use crate::pgn_parser::*;
+use crate::search::*;
 use crate::state::*;
+use crate::timeman::*;
+use crate::uci::*;
+use crate::utils::*;
+use crate::xboard::Xboard;
 use std::fs::File;
-use std::io::prelude::*;
 use std::io::BufReader;
+use std::io::prelude::*;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 use std::time::Instant;
 
 #[derive(Debug)]
@@ -90,6 +103,24 @@ pub fn run_check_hash_rec(path: &str) {
     }
 }
 
+pub fn run_check_null_move_rec(path: &str) {
+    // Run check_null_move_rec against test cases
+    let file = match File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(error) => panic!("Can't find {}: {:?}", path, error),
+    };
+
+    for line in file.lines() {
+        let test = parse_peft_test_case(&line.unwrap());
+        let mut state = State::generate_state_from_fen(&test.fen);
+        let max_depth = test
+            .values
+            .iter()
+            .fold(0, |acc, x| if x.depth > acc { x.depth } else { acc });
+        assert!(state.check_null_move_rec(max_depth.min(4)));
+    }
+}
+
 pub fn run_check_pst_eval_rec(path: &str) {
     // Run check_pst_eval_rec against test cases
     let file = match File::open(path) {
@@ -108,6 +139,49 @@ pub fn run_check_pst_eval_rec(path: &str) {
     }
 }
 
+// The incrementally kept up evaluation matches the one from scratch, at every node
+pub fn check_evaluator_rec<E: Evaluator>(
+    state: &mut State,
+    depth: usize,
+    evaluator: &mut E,
+) -> bool {
+    let mut ok = evaluator.eval(state) == evaluator.full_eval(state);
+
+    if depth > 1 {
+        let (legal_moves, _) = state.node_info();
+        let irs = state.ir_state();
+
+        for mv in &legal_moves {
+            state.make(mv);
+            evaluator.make(state, mv);
+            ok = ok && check_evaluator_rec(state, depth - 1, evaluator);
+            state.unmake(mv, &irs);
+            evaluator.unmake(state, mv);
+        }
+    }
+
+    ok && evaluator.eval(state) == evaluator.full_eval(state)
+}
+
+pub fn run_check_evaluator_rec<E: Evaluator>(path: &str, evaluator: &mut E) {
+    // Run check_evaluator_rec against test cases
+    let file = match File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(error) => panic!("Can't find {}: {:?}", path, error),
+    };
+
+    for line in file.lines() {
+        let test = parse_peft_test_case(&line.unwrap());
+        let mut state = State::generate_state_from_fen(&test.fen);
+        let max_depth = test
+            .values
+            .iter()
+            .fold(0, |acc, x| if x.depth > acc { x.depth } else { acc });
+        evaluator.init(&state);
+        assert!(check_evaluator_rec(&mut state, max_depth.min(4), evaluator));
+    }
+}
+
 pub fn run_check_is_legal_strict_rec(path: &str) {
     // Run check_is_legal_strict_rec against test cases
     let file = match File::open(path) {
@@ -126,6 +200,1399 @@ pub fn run_check_is_legal_strict_rec(path: &str) {
     }
 }
 
+// The picker yields every legal move exactly once - whatever the hash move and refutations are.
+// refutation is a move from the parent position, so it's often not even pseudo-legal here.
+pub fn check_move_picker_rec(state: &mut State, depth: usize, refutation: CompactMove) -> bool {
+    let legal_moves = state.legal_moves();
+    let history = [[0; 64]; 64];
+    let compact = |moves: &[Move]| {
+        let mut compact: Vec<u16> = moves.iter().map(|mv| mv.compact().0).collect();
+        compact.sort();
+        compact
+    };
+
+    let hash_move = legal_moves
+        .last()
+        .map_or(CompactMove::NONE, |mv| mv.compact());
+    let killers = [
+        refutation,
+        legal_moves
+            .first()
+            .map_or(CompactMove::NONE, |mv| mv.compact()),
+    ];
+    let mut picker = MovePicker::new(hash_move, killers, refutation, true);
+    let mut picked: Vec<Move> = Vec::new();
+    while let Some(mv) = picker.next(state, &history) {
+        picked.push(mv);
+    }
+
+    let mut picker = MovePicker::tactical();
+    let mut picked_tactical: Vec<Move> = Vec::new();
+    while let Some(mv) = picker.next(state, &history) {
+        picked_tactical.push(mv);
+    }
+
+    let mut ok = compact(&picked) == compact(&legal_moves)
+        && compact(&picked_tactical) == compact(&state.tactical_moves(&legal_moves));
+
+    if depth > 1 {
+        let irs = state.ir_state();
+        for mv in &legal_moves {
+            state.make(mv);
+            ok = ok && check_move_picker_rec(state, depth - 1, mv.compact());
+            state.unmake(mv, &irs);
+        }
+    }
+
+    ok
+}
+
+pub fn run_check_move_picker_rec(path: &str) {
+    // Run check_move_picker_rec against test cases
+    let file = match File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(error) => panic!("Can't find {}: {:?}", path, error),
+    };
+
+    for line in file.lines() {
+        let test = parse_peft_test_case(&line.unwrap());
+        let mut state = State::generate_state_from_fen(&test.fen);
+        let max_depth = test
+            .values
+            .iter()
+            .fold(0, |acc, x| if x.depth > acc { x.depth } else { acc });
+        assert!(check_move_picker_rec(
+            &mut state,
+            max_depth.min(3),
+            CompactMove::NONE
+        ));
+    }
+}
+
+pub fn shared_hash_table_rep() {
+    // Values round trip through the lock-free items, and the replacement scheme matches HashTable's
+    let tt: SharedHashTable<Eval> = SharedHashTable::new(4);
+    let mut ht: HashTable<Eval> = HashTable::new(4);
+    let state = State::new();
+    let best_move = state.node_info().0[0].compact();
+
+    for (i, value) in [-MATE_VALUE, -123, 0, 77, MATE_VALUE].iter().enumerate() {
+        for eval_type in [EvalType::Lower, EvalType::Upper, EvalType::Exact] {
+            let hash = state.hash.wrapping_mul(i as u64 + 1);
+            let eval = Eval::new(eval_type, *value, best_move);
+            tt.set(hash, i, eval);
+            ht.set(hash, i, eval);
+            assert_eq!(tt.probe(hash), ht.probe(hash));
+        }
+    }
+}
+
+pub fn hash_table_replacement_rep() {
+    // 4 buckets: hashes that are multiples of 4 share bucket 0
+    let mut ht: HashTable<Eval> = HashTable::new(4);
+    let eval = Eval::new(EvalType::Lower, 0, CompactMove::NONE);
+    let hash = |i: u64| 4 * (i + 1);
+
+    // A deep entry survives shallower stores of other positions, once the bucket is full
+    ht.new_search();
+    ht.set(hash(0), 10, eval);
+    for i in 1..BUCKET_SIZE as u64 + 2 {
+        ht.set(hash(i), i as usize, eval);
+    }
+    assert_eq!(ht.probe(hash(0)), Some((10, eval)));
+    assert_eq!(ht.probe(hash(1)), None);
+
+    // ... and a shallower (non-exact) store of the same position
+    ht.set(hash(0), 2, eval);
+    assert_eq!(ht.probe(hash(0)), Some((10, eval)));
+
+    // An entry from a few searches back goes first, however deep
+    ht.clear();
+    ht.new_search();
+    ht.set(hash(0), 6, eval);
+    ht.new_search();
+    ht.new_search();
+    for i in 1..BUCKET_SIZE as u64 {
+        ht.set(hash(i), 3, eval);
+    }
+    ht.set(hash(BUCKET_SIZE as u64), 2, eval);
+    assert_eq!(ht.probe(hash(0)), None);
+    for i in 1..=BUCKET_SIZE as u64 {
+        assert!(ht.probe(hash(i)).is_some());
+    }
+
+    // hashfull: the permille of the entries stored in this search
+    let mut ht: HashTable<Eval> = HashTable::new(12);
+    let tt: SharedHashTable<Eval> = SharedHashTable::new(12);
+    ht.clear();
+    tt.clear();
+    ht.new_search();
+    tt.new_search();
+    assert_eq!((ht.hashfull(), tt.hashfull()), (0, 0));
+
+    for i in 0..500 {
+        ht.set(i, 1, eval);
+        tt.set(i, 1, eval);
+    }
+    assert!(ht.hashfull() > 0);
+    assert_eq!(ht.hashfull(), tt.hashfull());
+
+    // ... which doesn't count the last search's
+    ht.new_search();
+    tt.new_search();
+    assert_eq!((ht.hashfull(), tt.hashfull()), (0, 0));
+}
+
+pub fn time_manager_rep() {
+    let clock = |wtime, btime, winc, binc, movestogo| TimeControl {
+        wtime,
+        btime,
+        winc,
+        binc,
+        movestogo,
+    };
+    let white = State::new();
+    let black = State::generate_state_from_fen(
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+    );
+
+    // ( clock, side to move, soft limit, hard limit ) - all in milliseconds, MOVE_OVERHEAD = 30 taken off first
+    let cases = [
+        // Sudden death: a 30th of the time, hard limit at 5x that
+        (
+            clock(Some(60000), Some(60000), 0, 0, None),
+            &white,
+            1999,
+            9995,
+        ),
+        // Increment: plus 3/4 of it
+        (
+            clock(Some(60000), Some(60000), 1000, 1000, None),
+            &white,
+            2749,
+            13745,
+        ),
+        // movestogo: the hard limit is capped at a 3rd of the time left
+        (
+            clock(Some(60000), Some(60000), 0, 0, Some(10)),
+            &white,
+            5997,
+            19990,
+        ),
+        (
+            clock(Some(60000), Some(60000), 0, 0, Some(0)),
+            &white,
+            19990,
+            19990,
+        ),
+        // Low on time, with an increment that's worth more than the clock: still within the time left
+        (
+            clock(Some(1000), Some(1000), 2000, 2000, None),
+            &white,
+            323,
+            323,
+        ),
+        // Black's clock for Black
+        (
+            clock(Some(60000), Some(30000), 1000, 0, None),
+            &black,
+            999,
+            4995,
+        ),
+        // No clock for our side
+        (
+            clock(Some(60000), None, 0, 0, None),
+            &black,
+            u64::MAX,
+            u64::MAX,
+        ),
+    ];
+
+    for (tc, state, soft_limit, hard_limit) in cases {
+        let time = TimeManager::new(&tc, state);
+        assert_eq!(
+            (time.soft_limit, time.hard_limit),
+            (soft_limit, hard_limit),
+            "{:?}",
+            tc
+        );
+        assert!(!time.forced);
+    }
+
+    // Plenty of time: we keep going
+    let tc = clock(Some(600000), Some(600000), 0, 0, None);
+    let mut time = TimeManager::new(&tc, &white);
+    let var = Variation::terminal(0);
+    assert!(!time.iteration_done(&var));
+
+    // Only one legal move (Kxg2): we stop after the first iteration, however much time we have
+    let fen = "7k/8/8/8/8/8/6q1/7K w - - 0 1";
+    let mut state = State::generate_state_from_fen(fen);
+    assert!(TimeManager::new(&tc, &state).forced);
+
+    let tt: SharedHashTable<Eval> = SharedHashTable::new(16);
+    let mut ctx = SearchContext::new();
+    ctx.time = Some(TimeManager::new(&tc, &state));
+    let mut depths = Vec::new();
+    let var = lazy_smp(&mut state, 1, &mut ctx, &tt, &mut |depth, _, _, _| {
+        depths.push(depth)
+    });
+    assert_eq!(depths, vec![1]);
+    assert_eq!(ctx.stop_reason, Some(StopReason::Time));
+    assert_eq!(var.pv_string(), "h1g2");
+}
+
+pub fn mate_solver_rep() {
+    // Mate in 2: 1. Nf6+ gxf6 2. Bxf7#
+    let fen = "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1";
+    let mut state = State::generate_state_from_fen(fen);
+    let mut tt: HashTable<ProofNumbers> = HashTable::new(16);
+
+    assert_eq!(solve_mate(&mut state, 1, &mut tt), MateResult::NoMate);
+    let result = solve_mate(&mut state, 3, &mut tt);
+    assert_eq!(result.mate_in(), Some(2));
+    if let MateResult::Mate(line) = result {
+        state.make_moves(&line);
+        assert_eq!(state.node_info().1, Status::Checkmate);
+    }
+}
+
+pub fn mate_args_rep() {
+    let args = |line: &str| -> Vec<String> { line.split_whitespace().map(String::from).collect() };
+    let fen = "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1";
+
+    let (max_moves, state) = crate::parse_mate_args(&args(&format!("3 {}", fen))).unwrap();
+    assert_eq!(max_moves, 3);
+    assert_eq!(state.fen(false), fen);
+    assert!(crate::parse_mate_args(&args(&format!("{} {}", MATE_SOLVER_MAX_MOVES, fen))).is_ok());
+
+    // Errors, not panics: bad or overflowing max_moves, and missing or malformed FENs
+    for line in [
+        "".to_string(),
+        "3".to_string(),
+        format!("0 {}", fen),
+        format!("x {}", fen),
+        format!("{} {}", MATE_SOLVER_MAX_MOVES + 1, fen),
+        format!("{} {}", usize::MAX, fen),
+        "3 r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP w KQkq - 1 1".to_string(),
+        "3 8/8/8/8/8/8/8/8 w - - 0 1".to_string(),
+    ] {
+        assert!(crate::parse_mate_args(&args(&line)).is_err(), "{}", line);
+    }
+}
+
+pub fn pawn_structure_rep() {
+    // a2 is isolated and splits the pawns into two islands; all of them are passed, and d5 is blocked by the knight
+    let fen = "4k3/8/3n4/3P4/8/8/P1P5/4K3 w - - 0 1";
+    let state = State::generate_state_from_fen(fen);
+    let passed = ["a2", "c2", "d5"]
+        .iter()
+        .fold(0, |bb, square| bb | 1 << algebraic_to_offset(square));
+
+    let pawns = pawn_eval(&state);
+    assert_eq!(
+        pawns,
+        PawnEval {
+            eval_mg: ISOLATED_PAWN_MG + PAWN_ISLAND_MG,
+            eval_eg: ISOLATED_PAWN_EG + PAWN_ISLAND_EG,
+            passed,
+        }
+    );
+    assert_eq!(
+        passed_pawns_eval(&state, pawns.passed),
+        (
+            2 * PASSED_PAWN_MG[1] + PASSED_PAWN_MG[4] * BLOCKED_PASSED_PAWN_PCT / 100,
+            2 * PASSED_PAWN_EG[1] + PASSED_PAWN_EG[4] * BLOCKED_PASSED_PAWN_PCT / 100
+        )
+    );
+}
+
+pub fn try_from_fen_rep() {
+    // Malformed FENs are rejected without panicking, valid ones round trip
+    for fen in [
+        "",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+        "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "rnbqkbnrr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq z9 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1",
+        "rnbqqbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ - 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQ1BNR w KQkq - 0 1",
+        "4k3/8/8/8/8/8/8/R3K3 w K - 0 1",
+        "4k3/8/8/8/8/8/8/P3K3 w - - 0 1",
+        "4k3/8/8/8/8/8/8/4K3 w k - 0 1",
+        "4k3/8/8/8/8/8/8/4R1K1 w - - 0 1",
+    ] {
+        assert!(State::try_from_fen(fen).is_err(), "Accepted: {}", fen);
+    }
+
+    for fen in [
+        START_FEN,
+        "rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b KQkq d3 0 3",
+        "4k3/8/8/8/8/8/8/4R1K1 b - - 0 1",
+    ] {
+        assert_eq!(State::try_from_fen(fen).unwrap().fen(false), fen);
+    }
+}
+
+pub fn enemy_king_pins_rep() {
+    // Pieces pinned to the enemy king are pinned by our sliders, never by their own
+    let e_file_pin = line_segment(4, 60) ^ (1 << 60);
+    let diagonal_pin = line_segment(0, 63) ^ (1 << 63);
+    for (fen, pinned_pos, pin) in [
+        ("4k3/8/8/4n3/8/8/8/4R1K1 w - - 0 1", 36, e_file_pin),
+        ("4k3/8/8/4n3/8/8/8/4Q1K1 w - - 0 1", 36, e_file_pin),
+        ("4k3/8/8/4n3/8/8/8/4r1K1 w - - 0 1", 36, FULL_BOARD),
+        ("4k3/8/8/4n3/8/8/8/4q1K1 w - - 0 1", 36, FULL_BOARD),
+        ("7k/8/8/4n3/8/8/8/B5K1 w - - 0 1", 36, diagonal_pin),
+        ("7k/8/8/4n3/8/8/8/b5K1 w - - 0 1", 36, FULL_BOARD),
+    ] {
+        let state = State::generate_state_from_fen(fen);
+        assert_eq!(state.a_pins[pinned_pos], pin, "{}", fen);
+    }
+}
+
+pub fn go_args_rep() {
+    // "ponder" takes no value - it mustn't swallow the clock
+    let go = GoArgs::parse(&[
+        "ponder", "wtime", "60000", "btime", "60000", "winc", "1000", "binc", "1000",
+    ]);
+    assert_eq!(
+        go,
+        GoArgs {
+            tc: TimeControl {
+                wtime: Some(60000),
+                btime: Some(60000),
+                winc: 1000,
+                binc: 1000,
+                movestogo: None,
+            },
+            ..Default::default()
+        }
+    );
+
+    // "searchmoves" runs till the next keyword
+    let go = GoArgs::parse(&["searchmoves", "e2e4", "d2d4", "depth", "3"]);
+    assert_eq!(go.searchmoves, vec!["e2e4", "d2d4"]);
+    assert_eq!(go.limits.depth, Some(3));
+    assert!(!go.infinite && !go.tc.is_set());
+}
+
+// Single threaded search of the FEN with the given limits: ( best Variation, nodes, stop reason )
+pub fn uci_drawn_root_rep() {
+    // Drawn by insufficient material, and by the fifty move rule - there are still legal moves to play
+    for fen in [
+        "8/8/8/8/8/2k5/8/K1N5 w - - 0 1",
+        "4k3/8/8/8/8/8/8/R3K3 w - - 100 80",
+    ] {
+        let mut uci = Uci::new();
+        uci.command("setoption name Hash value 1");
+        uci.command(&format!("position fen {}", fen));
+
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..Default::default()
+        };
+        let engine = uci.engine();
+        let best_move = search(engine, limits, Arc::new(AtomicBool::new(false)), None);
+        let mv = best_move.unwrap_or_else(|| panic!("No best move for {}", fen));
+        assert!(
+            engine.state.is_legal_strict(&mv),
+            "{}: {}",
+            fen,
+            mv.long_algebraic()
+        );
+    }
+}
+
+pub fn engine_pawn_table_rep() {
+    let mut uci = Uci::new();
+    uci.command("setoption name Hash value 1");
+    uci.command("position startpos");
+    let limits = SearchLimits {
+        depth: Some(3),
+        ..Default::default()
+    };
+
+    let engine = uci.engine();
+    let pawn_hash = engine.state.pawn_hash;
+    search(
+        engine,
+        limits.clone(),
+        Arc::new(AtomicBool::new(false)),
+        None,
+    );
+    let pawn_table = &engine.evaluator.as_ref().unwrap().pawn_table;
+    assert!(pawn_table.get(pawn_hash, 0).is_some());
+
+    // The next search can't get back to the starting pawns - they are still in the pawn hash table
+    uci.command("position startpos moves e2e4 e7e5");
+    let engine = uci.engine();
+    search(engine, limits, Arc::new(AtomicBool::new(false)), None);
+    let pawn_table = &engine.evaluator.as_ref().unwrap().pawn_table;
+    assert!(pawn_table.get(pawn_hash, 0).is_some());
+}
+
+// An Xboard that won't think on its own, with a small hash table
+pub fn quiet_xboard() -> Xboard {
+    let mut xboard = Xboard::new();
+    xboard.command("memory 1");
+    xboard.command("force");
+    xboard
+}
+
+pub fn xboard_fen(xboard: &mut Xboard) -> String {
+    xboard.game().engine.state.fen(false)
+}
+
+pub fn xboard_commands_rep() {
+    let start_fen = State::new().fen(false);
+    let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+
+    // setboard: a valid FEN replaces the position (and the move list), a malformed one keeps both
+    let mut xboard = quiet_xboard();
+    xboard.command("usermove e2e4");
+    xboard.command(&format!("setboard {}", fen));
+    assert_eq!(xboard_fen(&mut xboard), fen);
+    assert!(xboard.game().undo_list.is_empty());
+
+    xboard.command("usermove d2d4");
+    for bad_fen in [
+        "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP w KQkq - 2 3",
+        "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R x KQkq - 2 3",
+        "",
+    ] {
+        let before = xboard_fen(&mut xboard);
+        xboard.command(&format!("setboard {}", bad_fen));
+        assert_eq!(xboard_fen(&mut xboard), before, "{}", bad_fen);
+        assert_eq!(xboard.game().undo_list.len(), 1, "{}", bad_fen);
+    }
+
+    // usermove: coordinate notation and SAN both work; illegal moves change nothing
+    let mut xboard = quiet_xboard();
+    for mv_str in ["e2e4", "e5", "Nf3", "b8c6"] {
+        xboard.command(&format!("usermove {}", mv_str));
+    }
+    assert_eq!(xboard_fen(&mut xboard), fen);
+    for mv_str in ["e2e5", "Ke3", "e1g1", "xyz"] {
+        xboard.command(&format!("usermove {}", mv_str));
+        assert_eq!(xboard_fen(&mut xboard), fen, "{}", mv_str);
+    }
+    assert_eq!(xboard.game().undo_list.len(), 4);
+
+    // undo takes back one move, remove two; neither fails on an empty move list
+    xboard.command("undo");
+    assert_eq!(xboard.game().undo_list.len(), 3);
+    xboard.command("remove");
+    assert_eq!(xboard.game().undo_list.len(), 1);
+    xboard.command("remove");
+    assert_eq!(xboard_fen(&mut xboard), start_fen);
+    xboard.command("undo");
+    xboard.command("remove");
+    assert_eq!(xboard_fen(&mut xboard), start_fen);
+
+    // level MPS BASE INC, in centiseconds: BASE in minutes or minutes:seconds, INC in (fractional) seconds
+    let mut xboard = quiet_xboard();
+    xboard.command("st 10");
+    xboard.command("level 40 5 0");
+    assert_eq!(
+        (xboard.moves_per_session, xboard.base_time, xboard.increment),
+        (40, 30000, 0)
+    );
+    assert_eq!(xboard.time_per_move, 0);
+    xboard.command("level 0 5:30 2.5");
+    assert_eq!(
+        (xboard.moves_per_session, xboard.base_time, xboard.increment),
+        (0, 33000, 250)
+    );
+    xboard.command("level 0 2");
+    assert_eq!(xboard.base_time, 33000);
+
+    // st in seconds, sd at least 1
+    xboard.command("st 10");
+    assert_eq!(xboard.time_per_move, 1000);
+    xboard.command("sd 4");
+    assert_eq!(xboard.depth, Some(4));
+    xboard.command("sd 0");
+    assert_eq!(xboard.depth, Some(1));
+    xboard.command("sd x");
+    assert_eq!(xboard.depth, Some(1));
+
+    // The engine answers with a move, even in a drawn position
+    let mut xboard = quiet_xboard();
+    xboard.command("setboard 8/8/8/8/8/2k5/8/K1N5 w - - 0 1");
+    xboard.command("sd 2");
+    xboard.command("go");
+    xboard.wait();
+    assert_eq!(xboard.game.as_ref().unwrap().undo_list.len(), 1);
+}
+
+pub fn xboard_time_control_rep() {
+    let mut xboard = quiet_xboard();
+    xboard.command("level 40 5 0");
+    xboard.command("time 30000");
+
+    // Moves to go count down over the session, and start over at the next one
+    for (fullmove_count, movestogo) in [(1, 40), (2, 39), (40, 1), (41, 40), (80, 1), (81, 40)] {
+        for to_move in [WHITE, BLACK] {
+            let mut state = State::new();
+            state.fullmove_count = fullmove_count;
+            state.to_move = to_move;
+
+            let tc = xboard.time_control(&state);
+            assert_eq!(tc.movestogo, Some(movestogo), "move {}", fullmove_count);
+            if to_move == WHITE {
+                assert_eq!((tc.wtime, tc.btime), (Some(300000), None));
+            } else {
+                assert_eq!((tc.wtime, tc.btime), (None, Some(300000)));
+            }
+        }
+    }
+
+    // Increments are converted to milliseconds; no session means no moves to go
+    xboard.command("level 0 5 2");
+    let tc = xboard.time_control(&State::new());
+    assert_eq!(
+        tc,
+        TimeControl {
+            wtime: Some(300000),
+            winc: 2000,
+            ..Default::default()
+        }
+    );
+
+    // A fixed time per move, or no clock at all, leaves time management out of it
+    xboard.command("st 5");
+    assert!(!xboard.time_control(&State::new()).is_set());
+    let xboard = quiet_xboard();
+    assert!(!xboard.time_control(&State::new()).is_set());
+}
+
+pub fn search_with_limits(fen: &str, limits: SearchLimits) -> (Variation, u64, Option<StopReason>) {
+    let mut state = State::generate_state_from_fen(fen);
+    let tt: SharedHashTable<Eval> = SharedHashTable::new(16);
+    let mut ctx = SearchContext::new();
+    ctx.limits = limits;
+
+    let var = lazy_smp(&mut state, 1, &mut ctx, &tt, &mut |_, _, _, _| {});
+    (var, ctx.total_nodes(), ctx.stop_reason)
+}
+
+pub fn search_limits_rep() {
+    let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+
+    // Fixed-node searches are reproducible
+    let limits = SearchLimits {
+        nodes: Some(20000),
+        ..Default::default()
+    };
+    let (var, nodes, reason) = search_with_limits(fen, limits.clone());
+    let (var_again, nodes_again, _) = search_with_limits(fen, limits);
+    assert_eq!(reason, Some(StopReason::Nodes));
+    assert!(nodes >= 20000);
+    assert_eq!(nodes, nodes_again);
+    assert_eq!(var.move_list.front(), var_again.move_list.front());
+
+    // searchmoves confines the root move, even to a bad one
+    let state = State::generate_state_from_fen(fen);
+    let h2h3 = parse_long_algebraic("h2h3", &state).unwrap();
+    let limits = SearchLimits {
+        depth: Some(3),
+        searchmoves: vec![h2h3.compact()],
+        ..Default::default()
+    };
+    let (var, _, reason) = search_with_limits(fen, limits);
+    assert_eq!(reason, Some(StopReason::Depth));
+    assert!(h2h3.compact().matches(var.move_list.front().unwrap()));
+
+    // Stop as soon as we find a mate in 2: 1. Nf6+ gxf6 2. Bxf7#
+    let fen = "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1";
+    let limits = SearchLimits {
+        mate: Some(2),
+        ..Default::default()
+    };
+    let (var, _, reason) = search_with_limits(fen, limits);
+    assert_eq!(reason, Some(StopReason::Mate));
+    assert_eq!(var.move_list.front().unwrap().long_algebraic(), "d5f6");
+}
+
+pub fn mate_scores_rep() {
+    // A mate found at one ply is the same distance from the position when probed at another ply
+    let tt: SharedHashTable<Eval> = SharedHashTable::new(4);
+    let hash = State::new().hash;
+    for (stored_ply, probed_ply) in [(3, 3), (3, 7), (8, 2)] {
+        for (value, expected) in [
+            (mate_in(stored_ply + 5), mate_in(probed_ply + 5)),
+            (mated_in(stored_ply + 4), mated_in(probed_ply + 4)),
+            (123, 123),
+        ] {
+            tt.clear();
+            tt.set(
+                hash,
+                4,
+                Eval::new(
+                    EvalType::Exact,
+                    value_to_tt(value, stored_ply),
+                    CompactMove::NONE,
+                ),
+            );
+            let (_, hashed) = tt.probe(hash).unwrap();
+            assert_eq!(value_from_tt(hashed.value(), probed_ply), expected);
+        }
+    }
+
+    // Mate in 2: 1. Nf6+ gxf6 2. Bxf7#
+    let fen = "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1";
+    let limits = SearchLimits {
+        depth: Some(4),
+        ..Default::default()
+    };
+    let (var, _, _) = search_with_limits(fen, limits);
+    assert_eq!(Score::new(var.eval), Score::Mate(2));
+    assert_eq!(Score::new(var.eval).to_string(), "mate 2");
+
+    // ... and getting mated in 1, after 1. Nf6+ gxf6
+    let mut state = State::generate_state_from_fen(fen);
+    for mv_str in ["d5f6", "g7f6"] {
+        let mv = parse_long_algebraic(mv_str, &state).unwrap();
+        state.make(&mv);
+    }
+    let limits = SearchLimits {
+        depth: Some(2),
+        ..Default::default()
+    };
+    let (var, _, _) = search_with_limits(&state.fen(false), limits);
+    assert_eq!(Score::new(var.eval), Score::Mate(1));
+    assert_eq!(Score::new(-var.eval), Score::Mate(-1));
+}
+
+// Makes the moves (Long Algebraic Notation, "0000" for a null move), recording them in the context's path
+pub fn make_path(state: &mut State, ctx: &mut SearchContext, mv_strs: &[&str]) {
+    for (ply, mv_str) in mv_strs.iter().enumerate() {
+        if *mv_str == "0000" {
+            state.make_null();
+            ctx.path[ply] = None;
+        } else {
+            let mv = parse_long_algebraic(mv_str, state).unwrap();
+            state.make(&mv);
+            ctx.path[ply] = Some(mv);
+        }
+    }
+}
+
+pub fn killers_history_rep() {
+    let tt: SharedHashTable<Eval> = SharedHashTable::new(16);
+
+    // A quiet move fails high after ...Kg8: it's the killer, the countermove to ...Kg8, and its history goes up
+    let mut state = State::generate_state_from_fen("7k/6pp/8/8/8/8/PP6/4K3 b - - 0 1");
+    let mut ctx = SearchContext::new();
+    ctx.options = SearchOptions::none();
+    make_path(&mut state, &mut ctx, &["h8g8"]);
+    let var = negamax(&mut state, 1, 1, -1000, -999, &mut ctx, &tt);
+    let cutoff = *var.move_list.front().unwrap();
+    assert!(!state.is_tactical(&cutoff));
+    assert!(ctx.killers[1][0].matches(&cutoff));
+    assert!(ctx.countermove(1).matches(&cutoff));
+    assert!(ctx.history[WHITE as usize][cutoff.from][cutoff.to] > 0);
+
+    // A capture failing high is none of these
+    let mut state = State::generate_state_from_fen("7k/6pp/8/8/1p6/8/8/1R2K3 b - - 0 1");
+    let mut ctx = SearchContext::new();
+    ctx.options = SearchOptions::none();
+    make_path(&mut state, &mut ctx, &["h8g8"]);
+    let var = negamax(&mut state, 1, 1, -1000, -999, &mut ctx, &tt);
+    assert!(state.is_tactical(var.move_list.front().unwrap()));
+    assert_eq!(ctx.killers[1], [CompactMove::NONE; NUM_KILLERS]);
+    assert!(ctx.countermove(1).is_none());
+    assert!(ctx.history.iter().flatten().flatten().all(|&x| x == 0));
+
+    // Killers: the latest first, without duplicates; the quiet moves tried before the cutoff lose history
+    let state = State::new();
+    let mut ctx = SearchContext::new();
+    let mv = |mv_str: &str, state: &State| parse_long_algebraic(mv_str, state).unwrap();
+    let (e4, d4, c4) = (mv("e2e4", &state), mv("d2d4", &state), mv("c2c4", &state));
+    ctx.update_quiet_stats(0, 4, &e4, &[]);
+    ctx.update_quiet_stats(0, 4, &e4, &[]);
+    assert_eq!(ctx.killers[0], [e4.compact(), CompactMove::NONE]);
+    let e4_history = ctx.history[WHITE as usize][e4.from][e4.to];
+    ctx.update_quiet_stats(0, 4, &d4, &[e4]);
+    assert_eq!(ctx.killers[0], [d4.compact(), e4.compact()]);
+    ctx.update_quiet_stats(0, 4, &c4, &[]);
+    assert_eq!(ctx.killers[0], [c4.compact(), d4.compact()]);
+    let history = &ctx.history[WHITE as usize];
+    assert!(history[e4.from][e4.to] < e4_history);
+    assert!(history[d4.from][d4.to] > 0);
+
+    // ... which stays within bounds, however often it's updated
+    for _ in 0..1000 {
+        ctx.update_quiet_stats(0, MAX_DEPTH, &e4, &[d4]);
+    }
+    let history = &ctx.history[WHITE as usize];
+    assert!(history[e4.from][e4.to] <= HISTORY_MAX);
+    assert!(history[d4.from][d4.to] >= -HISTORY_MAX);
+
+    // The picker tries the killers, then the countermove, then the quiets by history
+    let nf3 = mv("g1f3", &state);
+    let mut history = [[0; 64]; 64];
+    history[nf3.from][nf3.to] = 100;
+    let mut picker = MovePicker::new(
+        CompactMove::NONE,
+        [c4.compact(), d4.compact()],
+        e4.compact(),
+        true,
+    );
+    for expected in [c4, d4, e4, nf3] {
+        assert!(
+            expected
+                .compact()
+                .matches(&picker.next(&state, &history).unwrap())
+        );
+    }
+}
+
+// ( eval, stats ) of a plain fixed depth search of the position, as if at the given ply, with a TT of its own
+pub fn search_node(
+    fen: &str,
+    depth: usize,
+    ply: usize,
+    options: SearchOptions,
+) -> (i32, SearchStats) {
+    let mut state = State::generate_state_from_fen(fen);
+    let tt: SharedHashTable<Eval> = SharedHashTable::new(16);
+    let mut ctx = SearchContext::new();
+    ctx.options = options;
+    ctx.evaluator.init(&state);
+
+    let var = negamax(&mut state, depth, ply, -INF_VALUE, INF_VALUE, &mut ctx, &tt);
+    (var.eval, ctx.stats)
+}
+
+pub fn pvs_rep() {
+    let file = BufReader::new(File::open("testing/perftsuite_lean.epd").unwrap());
+    let pvs = SearchOptions {
+        pvs: true,
+        ..SearchOptions::none()
+    };
+
+    // Null windows prove the rest of the moves no better than the first, or re-search the one that is:
+    // the root score is that of plain alpha-beta, with fewer nodes
+    let mut nodes = (0, 0);
+    let mut researched = false;
+    for line in file.lines() {
+        let fen = parse_peft_test_case(&line.unwrap()).fen;
+        let (eval, stats) = search_node(&fen, 4, 0, SearchOptions::none());
+        let (pvs_eval, pvs_stats) = search_node(&fen, 4, 0, pvs);
+        assert_eq!(pvs_eval, eval, "{}", fen);
+
+        assert_eq!(stats.pvs_null_window, 0);
+        assert!(pvs_stats.pvs_null_window > 0, "{}", fen);
+        researched = researched || pvs_stats.pvs_research > 0;
+        nodes.0 += stats.nodes();
+        nodes.1 += pvs_stats.nodes();
+    }
+    assert!(researched);
+    assert!(nodes.1 < nodes.0, "{:?}", nodes);
+}
+
+pub fn lmr_lmp_rep() {
+    let lmp = SearchOptions {
+        lmp: true,
+        ..SearchOptions::none()
+    };
+    let lmp_moves = |depth: usize| LMP_BASE_MOVES + depth * depth;
+
+    // Only quiet moves (9 of them), none of which give check: all but the first few are pruned - though never at the root
+    let quiet = "7k/6pp/8/8/8/8/PP6/4K3 w - - 0 1";
+    assert_eq!(
+        search_node(quiet, 1, 1, lmp).1.lmp as usize,
+        9 - lmp_moves(1)
+    );
+    assert_eq!(search_node(quiet, 1, 0, lmp).1.lmp, 0);
+
+    // Captures aren't pruned, even losing ones, which come after the 10 quiet moves
+    let capture = "7k/6pp/8/2p5/1p6/8/8/1R2K3 w - - 0 1";
+    assert_eq!(
+        search_node(capture, 1, 1, lmp).1.lmp as usize,
+        10 - lmp_moves(1)
+    );
+
+    // Neither are evasions (6 of them)
+    let in_check = "7k/6pp/8/8/1b6/8/PP6/1N2K3 w - - 0 1";
+    assert!(6 > lmp_moves(1));
+    assert_eq!(search_node(in_check, 1, 1, lmp).1.lmp, 0);
+
+    // Reductions grow with the depth and the move number
+    for depth in 1..=MAX_DEPTH {
+        for index in 1..LMR_MAX_MOVES {
+            assert!(LMR_TABLE[depth][index] >= LMR_TABLE[depth - 1][index]);
+            assert!(LMR_TABLE[depth][index] >= LMR_TABLE[depth][index - 1]);
+        }
+    }
+    assert!(LMR_TABLE[LMR_MIN_DEPTH][LMR_MIN_MOVES] > 0);
+
+    // Reduced moves that beat alpha are re-searched: the quiet Rb7 is found, with fewer nodes than without LMR
+    let lmr = SearchOptions {
+        lmr: true,
+        ..SearchOptions::none()
+    };
+    let test = parse_epd_test_case("7k/p7/1R5K/6r1/6p1/6P1/8/8 w - - bm Rb7; id \"WAC.006\";");
+    assert!(solves(&test, 5, lmr));
+
+    let file = BufReader::new(File::open("testing/perftsuite_lean.epd").unwrap());
+    let mut nodes = (0, 0);
+    let mut researched = false;
+    for line in file.lines() {
+        let fen = parse_peft_test_case(&line.unwrap()).fen;
+        let (_, stats) = search_node(&fen, 4, 0, SearchOptions::none());
+        let (_, lmr_stats) = search_node(&fen, 4, 0, lmr);
+        assert_eq!(stats.lmr, 0);
+        assert!(lmr_stats.lmr > 0, "{}", fen);
+        researched = researched || lmr_stats.lmr_research > 0;
+        nodes.0 += stats.nodes();
+        nodes.1 += lmr_stats.nodes();
+    }
+    assert!(researched);
+    assert!(nodes.1 < nodes.0, "{:?}", nodes);
+}
+
+pub fn search_repetition_rep() {
+    let cycle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+
+    // A single repetition within the search path draws
+    let mut state = State::new();
+    let mut ctx = SearchContext::new();
+    make_path(&mut state, &mut ctx, &cycle);
+    assert!(state.is_search_repetition(4, ctx.repetition_window(4)));
+
+    // ... but one in the game history (before the root) isn't enough
+    assert!(!state.is_search_repetition(0, usize::MAX));
+    assert!(!state.is_search_repetition(2, usize::MAX));
+
+    // ... it has to have been repeated twice
+    make_path(&mut state, &mut ctx, &cycle);
+    assert!(state.is_search_repetition(0, usize::MAX));
+
+    // Nothing before the last irreversible move (as per halfmove_clock) is looked at
+    let mut state = State::generate_state_from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+    let mut ctx = SearchContext::new();
+    make_path(&mut state, &mut ctx, &["e1d1", "e8d8", "d1e1", "d8e8"]);
+    assert!(state.is_search_repetition(4, usize::MAX));
+    state.halfmove_clock = 3;
+    assert!(!state.is_search_repetition(4, usize::MAX));
+
+    // ... and the fifty move rule draws once the clock reaches a hundred
+    let mut state = State::generate_state_from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 98 60");
+    let mut ctx = SearchContext::new();
+    make_path(&mut state, &mut ctx, &["e1d1"]);
+    assert_eq!(state.draw_status(), Status::Ongoing);
+    make_path(&mut state, &mut ctx, &["e8d8"]);
+    assert_eq!(state.draw_status(), Status::FiftyMoveDraw);
+
+    // A repetition straddling a null move doesn't count
+    let mut state = State::new();
+    let mut ctx = SearchContext::new();
+    make_path(
+        &mut state,
+        &mut ctx,
+        &["g1f3", "0000", "f3g1", "b8c6", "0000", "c6b8"],
+    );
+    assert_eq!(state.hash, State::new().hash);
+    assert!(state.is_search_repetition(6, usize::MAX));
+    assert_eq!(ctx.repetition_window(6), 1);
+    assert!(!state.is_search_repetition(6, ctx.repetition_window(6)));
+}
+
+// The same position with the colours swapped: ranks mirrored, pieces, side to move and castling rights swapped
+pub fn en_passant_tactical_rep() {
+    // exd6 e.p. is the only capture - and it wins a pawn
+    let fen = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2";
+    let mut state = State::generate_state_from_fen(fen);
+    let ep_capture = parse_long_algebraic("e5d6", &state).unwrap();
+    assert!(state.is_tactical(&ep_capture));
+
+    // It's generated with the tactical moves, not the quiet ones
+    let mut tactical = Vec::new();
+    let mut quiet = Vec::new();
+    state.tactical_pl_moves(&mut tactical);
+    state.quiet_pl_moves(&mut quiet);
+    assert_eq!(tactical.len(), 1);
+    assert!(ep_capture.compact().matches(&tactical[0]));
+    assert!(!quiet.iter().any(|mv| ep_capture.compact().matches(mv)));
+
+    let mut picker = MovePicker::tactical();
+    let history = [[0; 64]; 64];
+    assert!(
+        ep_capture
+            .compact()
+            .matches(&picker.next(&state, &history).unwrap())
+    );
+    assert!(picker.next(&state, &history).is_none());
+
+    // So quiescence finds it
+    let mut ctx = SearchContext::new();
+    let stand_pat = ctx.evaluator.eval(&state);
+    let eval = quiescence(&mut state, 0, 0, -INF_VALUE, INF_VALUE, &mut ctx);
+    assert!(
+        eval >= stand_pat + PAWN_VALUE_MG / 2,
+        "{} vs {}",
+        eval,
+        stand_pat
+    );
+}
+
+pub fn quiescence_rep() {
+    let qsearch = |fen: &str, alpha: i32, beta: i32, options: SearchOptions| {
+        let mut state = State::generate_state_from_fen(fen);
+        let mut ctx = SearchContext::new();
+        ctx.options = options;
+        let stand_pat = ctx.evaluator.eval(&state);
+        let eval = quiescence(&mut state, 2, 0, alpha, beta, &mut ctx);
+        (stand_pat, eval, ctx.stats)
+    };
+
+    // Mated: no standing pat
+    let (_, eval, _) = qsearch(
+        "R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1",
+        -INF_VALUE,
+        INF_VALUE,
+        SearchOptions::none(),
+    );
+    assert_eq!(eval, mated_in(2));
+
+    // In check, the (quiet) evasions are searched: Black is a queen up, but the fork wins it
+    let (stand_pat, eval, _) = qsearch(
+        "q3k3/2N4p/8/8/8/8/7P/6K1 b - - 0 1",
+        -INF_VALUE,
+        INF_VALUE,
+        SearchOptions::none(),
+    );
+    assert!(stand_pat > 0 && eval < 0, "{} vs {}", eval, stand_pat);
+
+    // Quiet checks at the first ply, if asked for: Ra8 mates
+    let fen = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+    let (stand_pat, eval, stats) = qsearch(fen, -INF_VALUE, INF_VALUE, SearchOptions::none());
+    assert_eq!((eval, stats.checks_qs), (stand_pat, 0));
+    let options = SearchOptions {
+        qs_checks: true,
+        ..SearchOptions::none()
+    };
+    let (_, eval, stats) = qsearch(fen, -INF_VALUE, INF_VALUE, options);
+    assert_eq!(eval, mate_in(3));
+    assert!(stats.checks_qs > 0);
+
+    // SEE Pruning skips Qxd5 (the queen for a pawn) wherever it comes up, but not Qxh5
+    let fen = "4k3/8/4p3/3p3p/8/8/8/3QK3 w - - 0 1";
+    let (stand_pat, eval, stats) = qsearch(fen, -INF_VALUE, INF_VALUE, SearchOptions::none());
+    assert_eq!(stats.see_pruned_qs, 0);
+    let options = SearchOptions {
+        qs_see: true,
+        ..SearchOptions::none()
+    };
+    let (_, see_eval, stats) = qsearch(fen, -INF_VALUE, INF_VALUE, options);
+    assert!(stats.see_pruned_qs > 0);
+    assert_eq!(see_eval, eval);
+    assert!(eval > stand_pat);
+
+    // Delta Pruning skips a pawn capture when alpha is out of reach, but not a promotion
+    let options = SearchOptions {
+        delta: true,
+        ..SearchOptions::none()
+    };
+    let fen = "4k3/8/8/7p/8/8/8/3QK3 w - - 0 1";
+    let (stand_pat, _, _) = qsearch(fen, -INF_VALUE, INF_VALUE, options);
+    let (_, _, stats) = qsearch(
+        fen,
+        stand_pat + QUEEN_VALUE_MG,
+        stand_pat + QUEEN_VALUE_MG + 1,
+        options,
+    );
+    assert_eq!(stats.delta_pruned_qs, 1);
+    let fen = "4k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+    let (stand_pat, _, _) = qsearch(fen, -INF_VALUE, INF_VALUE, options);
+    let (_, _, stats) = qsearch(
+        fen,
+        stand_pat + PAWN_VALUE_MG,
+        stand_pat + PAWN_VALUE_MG + 1,
+        options,
+    );
+    assert_eq!(stats.delta_pruned_qs, 0);
+}
+
+pub fn flip_fen(fen: &str) -> String {
+    let swap_case = |c: char| {
+        if c.is_ascii_uppercase() {
+            c.to_ascii_lowercase()
+        } else {
+            c.to_ascii_uppercase()
+        }
+    };
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+
+    let board: Vec<String> = fields[0]
+        .rsplit('/')
+        .map(|row| row.chars().map(swap_case).collect())
+        .collect();
+    let to_move = if fields[1] == "w" { "b" } else { "w" };
+    let mut castling: Vec<char> = fields[2].chars().map(swap_case).collect();
+    castling.sort_by_key(|c| "KQkq-".find(*c));
+    let en_passant = match fields[3].as_bytes() {
+        [file, rank] => format!("{}{}", *file as char, (b'9' - rank + b'0') as char),
+        _ => fields[3].to_string(),
+    };
+
+    let mut flipped = vec![
+        board.join("/"),
+        to_move.to_string(),
+        castling.into_iter().collect(),
+        en_passant,
+    ];
+    flipped.extend(fields[4..].iter().map(|x| x.to_string()));
+    flipped.join(" ")
+}
+
+pub fn mobility_symmetry_rep() {
+    // Pins and x-rays through the king, on either side of the board
+    let fens = [
+        "4k3/4n3/8/8/8/8/4R3/4K3 w - - 0 1",
+        "4k3/5b2/8/8/8/8/8/Q3K3 w - - 0 1",
+        "4k3/4r3/8/8/8/8/4N3/4K3 w - - 0 1",
+        "4k3/4r3/8/8/8/8/4N3/4K3 b - - 0 1",
+        "4k3/8/2b5/8/8/8/6Q1/7K w - - 0 1",
+        "3rk3/8/8/8/3K4/8/3B4/8 w - - 0 1",
+    ];
+    let file = BufReader::new(File::open("testing/perftsuite_lean.epd").unwrap());
+    let lean: Vec<String> = file
+        .lines()
+        .map(|line| parse_peft_test_case(&line.unwrap()).fen)
+        .collect();
+
+    for fen in fens.iter().copied().chain(lean.iter().map(|x| x.as_str())) {
+        let state = State::generate_state_from_fen(fen);
+        let flipped = State::generate_state_from_fen(&flip_fen(fen));
+        let (mg, eg) = mobility_eval(&state);
+        assert_eq!(mobility_eval(&flipped), (-mg, -eg), "{}", fen);
+
+        // Whoever is to move - unless that's illegal
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let to_move = if fields[1] == "w" { "b" } else { "w" };
+        let other = format!("{} {} {} - 0 1", fields[0], to_move, fields[2]);
+        if let Ok(other) = State::try_from_fen(&other) {
+            assert_eq!(mobility_eval(&other), (mg, eg), "{}", fen);
+        }
+    }
+}
+
+pub fn piece_control_maps_rep() {
+    // Checks along a line, with squares beyond the king and behind the checker
+    let fens = [
+        "4k3/8/8/8/8/8/8/q3K2R w K - 0 1",
+        "8/4k3/8/8/4Q3/8/8/4K3 b - - 0 1",
+        "K7/8/4Q3/8/8/8/4k3/8 b - - 0 1",
+        "7k/8/8/8/3b4/8/8/K7 w - - 0 1",
+        "k7/8/8/8/8/5K2/8/7b w - - 0 1",
+    ];
+    let file = BufReader::new(File::open("testing/perftsuite_lean.epd").unwrap());
+    let lean: Vec<String> = file
+        .lines()
+        .map(|line| parse_peft_test_case(&line.unwrap()).fen)
+        .collect();
+
+    // The control maps, less the x-rays, are the attacks on the board as it is
+    for fen in fens.iter().copied().chain(lean.iter().map(|x| x.as_str())) {
+        let state = State::generate_state_from_fen(fen);
+        let occupancy = state.bit_board[WHITE_ALL] | state.bit_board[BLACK_ALL];
+        for color in [WHITE, BLACK] {
+            for piece_type in [KNIGHT, BISHOP, ROOK, QUEEN] {
+                let mut bb = state.bit_board[color | piece_type];
+                while bb != 0 {
+                    let pos = pop_lsb_pos(&mut bb);
+                    let attacks = match piece_type {
+                        KNIGHT => state.mg.n_moves(pos),
+                        BISHOP => state.mg.b_moves(pos, occupancy),
+                        ROOK => state.mg.r_moves(pos, occupancy),
+                        _ => state.mg.q_moves(pos, occupancy),
+                    };
+                    assert_eq!(piece_control(&state, pos), attacks, "{} at {}", fen, pos);
+                }
+            }
+        }
+    }
+}
+
+pub fn multi_pv_rep() {
+    // Rxd5 wins the queen; everything else doesn't
+    let fen = "3qk3/8/8/3q4/8/8/3R4/3RK3 w - - 0 1";
+    let mut state = State::generate_state_from_fen(fen);
+    let tt: SharedHashTable<Eval> = SharedHashTable::new(16);
+    let mut ctx = SearchContext::new();
+    ctx.limits.depth = Some(3);
+    ctx.options = SearchOptions::none();
+
+    let lines = multi_pv(&mut state, 3, &mut ctx, &tt, &mut |_, _, _, _| {});
+    assert_eq!(ctx.stop_reason, Some(StopReason::Depth));
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0].move_list.front().unwrap().long_algebraic(), "d2d5");
+    for pair in lines.windows(2) {
+        assert!(pair[0].eval >= pair[1].eval);
+        assert_ne!(pair[0].move_list.front(), pair[1].move_list.front());
+    }
+    assert_ne!(lines[0].move_list.front(), lines[2].move_list.front());
+
+    // Exact scores: the same as searching each of the moves on its own, without any pruning to make the search path matter
+    for var in &lines {
+        let mut state = State::generate_state_from_fen(fen);
+        let tt: SharedHashTable<Eval> = SharedHashTable::new(16);
+        let mut ctx = SearchContext::new();
+        ctx.options = SearchOptions::none();
+        ctx.limits.depth = Some(3);
+        ctx.limits.searchmoves = vec![var.move_list.front().unwrap().compact()];
+
+        let single = lazy_smp(&mut state, 1, &mut ctx, &tt, &mut |_, _, _, _| {});
+        assert_eq!(single.eval, var.eval, "{}", var.pv_string());
+    }
+}
+
+pub fn singular_extension_rep() {
+    // Rxd8+ Kxd8: the recapture is the only move that doesn't just lose a rook
+    let fen = "3rk3/ppp2ppp/8/8/8/8/PPP2PPP/3RK3 w - - 0 1";
+
+    for singular in [true, false] {
+        let mut state = State::generate_state_from_fen(fen);
+        let tt: SharedHashTable<Eval> = SharedHashTable::new(20);
+        let mut ctx = SearchContext::new();
+        ctx.options.singular = singular;
+        ctx.limits.depth = Some(9);
+
+        let var = lazy_smp(&mut state, 1, &mut ctx, &tt, &mut |_, _, _, _| {});
+        assert!(var.pv_string().starts_with("d1d8 e8d8"));
+        if singular {
+            assert!(ctx.stats.singular_ext > 0);
+            assert!(ctx.stats.singular_ext <= ctx.stats.singular);
+        } else {
+            assert_eq!(ctx.stats.singular, 0);
+        }
+    }
+}
+
+pub fn king_safety_rep() {
+    // Black's king is the same throughout, and out of reach - only White's changes
+    let safety = |fen: &str| king_safety_eval(&State::generate_state_from_fen(fen));
+    let intact = safety("k7/pp6/8/8/8/8/5PPP/6K1 w - - 0 1");
+
+    // Pushed shield pawn
+    assert_eq!(
+        safety("k7/pp6/8/8/8/6P1/5P1P/6K1 w - - 0 1") - intact,
+        KING_SHIELD_PAWN[1] - KING_SHIELD_PAWN[0]
+    );
+
+    // Open and semi-open files next to the king (the latter with a storming pawn)
+    assert_eq!(
+        safety("k7/pp6/8/8/8/8/5P1P/6K1 w - - 0 1") - intact,
+        KING_OPEN_FILE - KING_SHIELD_PAWN[0]
+    );
+    assert_eq!(
+        safety("k7/pp6/8/6p1/8/8/5P1P/6K1 w - - 0 1") - intact,
+        KING_SEMI_OPEN_FILE + KING_STORM_PAWN[3] - KING_SHIELD_PAWN[0]
+    );
+
+    // Pawn storm
+    assert_eq!(
+        safety("k7/pp6/8/8/7p/8/5PPP/6K1 w - - 0 1") - intact,
+        KING_STORM_PAWN[2]
+    );
+
+    // Attacks on the king zone: a lone Rook (on g3, g2) isn't an attack; with the Queen (on g3, h2), it is
+    assert_eq!(safety("k5r1/pp6/8/8/8/8/5PPP/6K1 w - - 0 1"), intact);
+    assert_eq!(
+        safety("k5r1/pp6/3q4/8/8/8/5PPP/6K1 w - - 0 1") - intact,
+        -KING_SAFETY_TABLE[2 * KING_ATTACKER_WEIGHT[2] + 2 * KING_ATTACKER_WEIGHT[3]]
+    );
+
+    // The same, whoever is to move, and for either colour
+    for fen in [
+        "k5r1/pp6/3q4/8/8/8/5PPP/6K1 w - - 0 1",
+        "k5r1/pp6/3q4/8/8/8/5PPP/6K1 b - - 0 1",
+        "6k1/5ppp/8/8/8/2N5/1B3PPP/3R2K1 w - - 0 1",
+    ] {
+        let flipped = flip_fen(fen);
+        assert_eq!(safety(&flipped), -safety(fen), "{}", fen);
+    }
+}
+
+pub fn piece_terms_rep() {
+    // Pairs of positions that differ in a single term: ( with, without ), its ( mg, eg ), and whether it's a bonus or a penalty
+    let pieces = |fen: &str| piece_eval(&State::generate_state_from_fen(fen));
+    let cases = [
+        // Outposts: the f-pawn could chase the minor piece away
+        (
+            "4k3/p6p/8/4N3/3P4/8/8/4K3 w - - 0 1",
+            "4k3/p4p1p/8/4N3/3P4/8/8/4K3 w - - 0 1",
+            (KNIGHT_OUTPOST_MG, KNIGHT_OUTPOST_EG),
+            1,
+        ),
+        (
+            "4k3/p6p/8/4B3/3P4/8/8/4K3 w - - 0 1",
+            "4k3/p4p1p/8/4B3/3P4/8/8/4K3 w - - 0 1",
+            (BISHOP_OUTPOST_MG, BISHOP_OUTPOST_EG),
+            1,
+        ),
+        // Rook on an open file, and on a semi-open one
+        (
+            "4k3/8/8/8/8/8/8/3RK3 w - - 0 1",
+            "4k3/3p4/8/8/8/8/3P4/3RK3 w - - 0 1",
+            (ROOK_OPEN_FILE_MG, ROOK_OPEN_FILE_EG),
+            1,
+        ),
+        (
+            "4k3/3p4/8/8/8/8/8/3RK3 w - - 0 1",
+            "4k3/3p4/8/8/8/8/3P4/3RK3 w - - 0 1",
+            (ROOK_SEMI_OPEN_FILE_MG, ROOK_SEMI_OPEN_FILE_EG),
+            1,
+        ),
+        // Rook on the 7th, with the enemy king on the 8th
+        (
+            "4k3/3R4/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/3R4/8/8/8/8/4K3 w - - 0 1",
+            (ROOK_ON_SEVENTH_MG, ROOK_ON_SEVENTH_EG),
+            1,
+        ),
+        // Bad Bishop: two pawns on its colour (b2, d2)
+        (
+            "4k3/8/8/8/8/8/1P1P4/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/P1P5/2B1K3 w - - 0 1",
+            (2 * BAD_BISHOP_PAWN_MG, 2 * BAD_BISHOP_PAWN_EG),
+            -1,
+        ),
+        // Early Queen: all four minor pieces at home
+        (
+            "4k3/8/8/8/7Q/8/8/1NB1KBN1 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1NBQKBN1 w - - 0 1",
+            (4 * EARLY_QUEEN_MG, 4 * EARLY_QUEEN_EG),
+            -1,
+        ),
+        // Bishop trapped on a7 by b6
+        (
+            "4k3/B7/1p6/8/8/8/8/4K3 w - - 0 1",
+            "4k3/B7/8/8/8/8/8/4K3 w - - 0 1",
+            (TRAPPED_BISHOP_MG, TRAPPED_BISHOP_EG),
+            -1,
+        ),
+        // Rook shut in by Kf1
+        (
+            "4k3/8/8/8/8/8/8/5K1R w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K2R w - - 0 1",
+            (TRAPPED_ROOK_MG, TRAPPED_ROOK_EG),
+            -1,
+        ),
+    ];
+
+    for (with, without, (term_mg, term_eg), sign) in cases {
+        let (with_mg, with_eg) = pieces(with);
+        let (without_mg, without_eg) = pieces(without);
+        assert_eq!((with_mg - without_mg).signum(), sign, "{}", with);
+        assert_eq!(
+            (with_mg - without_mg, with_eg - without_eg),
+            (term_mg, term_eg),
+            "{}",
+            with
+        );
+
+        // ... and the same for Black
+        assert_eq!(pieces(&flip_fen(with)), (-with_mg, -with_eg), "{}", with);
+    }
+}
+
+// EPD test position: "<fen without the clocks> bm <san> ...; id \"<id>\";"
+#[derive(Debug)]
+pub struct EPDTestCase {
+    pub fen: String,
+    pub best_moves: Vec<String>,
+    pub id: String,
+}
+
+pub fn parse_epd_test_case(test: &str) -> EPDTestCase {
+    let fields: Vec<&str> = test.split_whitespace().collect();
+    assert!(fields.len() > 4, "Invalid test case: {}", test);
+
+    let mut best_moves = Vec::new();
+    let mut id = String::new();
+    for operation in fields[4..].join(" ").split(';') {
+        let mut iter = operation.split_whitespace();
+        match iter.next() {
+            Some("bm") => best_moves.extend(iter.map(|x| x.to_string())),
+            Some("id") => id = iter.collect::<Vec<&str>>().join(" ").replace('"', ""),
+            _ => {}
+        }
+    }
+
+    assert!(!best_moves.is_empty(), "No best move to test for: {}", test);
+    EPDTestCase {
+        fen: format!("{} 0 1", fields[..4].join(" ")),
+        best_moves,
+        id,
+    }
+}
+
+// Does a fixed depth search with the given options find (one of) the best moves?
+pub fn solves(test: &EPDTestCase, depth: usize, options: SearchOptions) -> bool {
+    let mut state = State::generate_state_from_fen(&test.fen);
+    let best_moves: Vec<Move> = test
+        .best_moves
+        .iter()
+        .map(|san| parse_move(san, &state).unwrap())
+        .collect();
+
+    let tt: SharedHashTable<Eval> = SharedHashTable::new(20);
+    let mut ctx = SearchContext::new();
+    ctx.options = options;
+    ctx.limits.depth = Some(depth);
+
+    let var = lazy_smp(&mut state, 1, &mut ctx, &tt, &mut |_, _, _, _| {});
+    best_moves
+        .iter()
+        .any(|mv| mv.compact().matches(var.move_list.front().unwrap()))
+}
+
+// The first num_positions of testing/tactics.epd, with all options on and then each option toggled in turn.
+// At depth 7, every position is found whichever option is toggled; the mates up front, at depth 5 already.
+pub fn tactics_rep(num_positions: usize, depth: usize) {
+    type Toggle = fn(&mut SearchOptions);
+    let variants: [(&str, Toggle); 13] = [
+        ("all options", |_| {}),
+        ("no pvs", |o| o.pvs = false),
+        ("no null_move", |o| o.null_move = false),
+        ("no lmr", |o| o.lmr = false),
+        ("no lmp", |o| o.lmp = false),
+        ("no delta", |o| o.delta = false),
+        ("no qs_see", |o| o.qs_see = false),
+        ("no rfp", |o| o.rfp = false),
+        ("no futility", |o| o.futility = false),
+        ("no razoring", |o| o.razoring = false),
+        ("no check_ext", |o| o.check_ext = false),
+        ("no singular", |o| o.singular = false),
+        ("qs_checks", |o| o.qs_checks = true),
+    ];
+
+    let file = BufReader::new(File::open("testing/tactics.epd").unwrap());
+    for line in file.lines().take(num_positions) {
+        let test = parse_epd_test_case(&line.unwrap());
+        for (name, toggle) in variants {
+            let mut options = SearchOptions::new();
+            toggle(&mut options);
+            assert!(
+                solves(&test, depth, options),
+                "{} not solved with {}",
+                test.id,
+                name
+            );
+        }
+    }
+}
+
 pub fn perftsuite_bench() {
     let start = Instant::now();
     run_perft("testing/perftsuite_bench.epd", true);
@@ -171,12 +1638,177 @@ pub fn test_check_hash_rec() {
     run_check_hash_rec("testing/perftsuite_lean.epd");
 }
 
+#[test]
+pub fn test_check_null_move_rec() {
+    run_check_null_move_rec("testing/perftsuite_lean.epd");
+}
+
 #[test]
 pub fn test_check_pst_eval_rec() {
     run_check_pst_eval_rec("testing/perftsuite_lean.epd");
 }
 
+#[test]
+pub fn test_check_evaluator_rec() {
+    run_check_evaluator_rec("testing/perftsuite_lean.epd", &mut PSTEvaluator);
+    run_check_evaluator_rec(
+        "testing/perftsuite_lean.epd",
+        &mut HeuristicEvaluator::new(),
+    );
+}
+
 #[test]
 pub fn test_check_is_legal_strict_rec() {
     run_check_is_legal_strict_rec("testing/perftsuite_lean.epd");
 }
+
+#[test]
+pub fn test_check_move_picker_rec() {
+    run_check_move_picker_rec("testing/perftsuite_lean.epd");
+}
+
+#[test]
+pub fn shared_hash_table() {
+    shared_hash_table_rep()
+}
+
+#[test]
+pub fn mate_solver() {
+    mate_solver_rep()
+}
+
+#[test]
+pub fn mate_args() {
+    mate_args_rep()
+}
+
+#[test]
+pub fn pawn_structure() {
+    pawn_structure_rep()
+}
+
+#[test]
+pub fn try_from_fen() {
+    try_from_fen_rep()
+}
+
+#[test]
+pub fn enemy_king_pins() {
+    enemy_king_pins_rep()
+}
+
+#[test]
+pub fn go_args() {
+    go_args_rep()
+}
+
+#[test]
+pub fn uci_drawn_root() {
+    uci_drawn_root_rep()
+}
+
+#[test]
+pub fn engine_pawn_table() {
+    engine_pawn_table_rep()
+}
+
+#[test]
+pub fn xboard_commands() {
+    xboard_commands_rep()
+}
+
+#[test]
+pub fn xboard_time_control() {
+    xboard_time_control_rep()
+}
+
+#[test]
+pub fn search_limits() {
+    search_limits_rep()
+}
+
+#[test]
+pub fn mate_scores() {
+    mate_scores_rep()
+}
+
+#[test]
+pub fn pvs() {
+    pvs_rep()
+}
+
+#[test]
+pub fn lmr_lmp() {
+    lmr_lmp_rep()
+}
+
+#[test]
+pub fn killers_history() {
+    killers_history_rep()
+}
+
+#[test]
+pub fn quiescence_evasions() {
+    quiescence_rep()
+}
+
+#[test]
+pub fn search_repetition() {
+    search_repetition_rep()
+}
+
+#[test]
+pub fn en_passant_tactical() {
+    en_passant_tactical_rep()
+}
+
+#[test]
+pub fn mobility_symmetry() {
+    mobility_symmetry_rep()
+}
+
+#[test]
+pub fn piece_control_maps() {
+    piece_control_maps_rep()
+}
+
+#[test]
+pub fn multi_pv_lines() {
+    multi_pv_rep()
+}
+
+#[test]
+pub fn singular_extension() {
+    singular_extension_rep()
+}
+
+#[test]
+pub fn king_safety() {
+    king_safety_rep()
+}
+
+#[test]
+pub fn piece_terms() {
+    piece_terms_rep()
+}
+
+#[test]
+pub fn hash_table_replacement() {
+    hash_table_replacement_rep()
+}
+
+#[test]
+pub fn time_manager() {
+    time_manager_rep()
+}
+
+#[test]
+pub fn tactics() {
+    tactics_rep(4, 5)
+}
+
+#[test]
+#[ignore]
+pub fn tactics_full() {
+    tactics_rep(usize::MAX, 7)
+}
@@ -0,0 +1,137 @@
+//! Time Management: Decide how long to think on a move
+
+use crate::consts::*;
+use crate::search::*;
+use crate::state::*;
+use std::cmp;
+use std::time::Instant;
+
+/* Times in milliseconds */
+
+// Reserved for communication lag etc.
+pub const MOVE_OVERHEAD: u64 = 30;
+
+// Assume this many moves till the next time control, if not told otherwise (sudden death)
+pub const DEFAULT_MOVES_TO_GO: u64 = 30;
+
+// The hard limit is at most this multiple of the soft limit, and never more than this fraction of the time left
+pub const HARD_LIMIT_FACTOR: u64 = 5;
+pub const HARD_LIMIT_FRACTION: u64 = 3;
+
+// Extensions of the soft limit, in percent: when the best move changes, or the score drops by more than SCORE_DROP
+pub const BEST_MOVE_CHANGE_EXTENSION: u64 = 60;
+pub const SCORE_DROP_EXTENSION: u64 = 40;
+pub const MAX_EXTENSION: u64 = 200;
+pub const SCORE_DROP: i32 = 30;
+
+// Check the clock once every these many nodes
+pub const TIME_CHECK_INTERVAL: u64 = 1024;
+
+// Clock info as received from the GUI
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TimeControl {
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: u64,
+    pub binc: u64,
+    pub movestogo: Option<u64>,
+}
+
+impl TimeControl {
+    // Do we need to manage time at all?
+    pub fn is_set(&self) -> bool {
+        self.wtime.is_some() || self.btime.is_some()
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TimeManager {
+    pub start: Instant,
+    pub soft_limit: u64, // Don't start a new iteration once we are past (an extension of) this
+    pub hard_limit: u64, // Abort the search once we are past this
+    pub extension: u64,  // Extension of the soft limit in percent
+    pub forced: bool,    // Only one legal move
+    pub best_move: Option<Move>,
+    pub best_eval: i32,
+}
+
+impl TimeManager {
+    pub fn new(tc: &TimeControl, state: &State) -> Self {
+        let (time, inc) = if state.to_move == WHITE {
+            (tc.wtime, tc.winc)
+        } else {
+            (tc.btime, tc.binc)
+        };
+
+        let (soft_limit, hard_limit) = match time {
+            Some(time) => {
+                let available = time.saturating_sub(MOVE_OVERHEAD);
+                let moves_to_go = tc.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+                let soft = cmp::min(available / moves_to_go + inc * 3 / 4, available);
+                let hard = cmp::min(soft * HARD_LIMIT_FACTOR, available / HARD_LIMIT_FRACTION);
+                (cmp::min(soft, hard), hard)
+            }
+            None => (u64::MAX, u64::MAX), // No clock for our side
+        };
+
+        TimeManager {
+            start: Instant::now(),
+            soft_limit,
+            hard_limit,
+            extension: 0,
+            forced: state.node_info().0.len() == 1,
+            best_move: None,
+            best_eval: -INF_VALUE,
+        }
+    }
+
+    // Elapsed time in milliseconds
+    #[inline]
+    pub fn elapsed(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    #[inline]
+    pub fn hard_limit_exceeded(&self) -> bool {
+        self.elapsed() >= self.hard_limit
+    }
+
+    // Soft limit, including extensions, but never beyond the hard limit
+    pub fn optimum(&self) -> u64 {
+        cmp::min(
+            self.soft_limit.saturating_mul(100 + self.extension) / 100,
+            self.hard_limit,
+        )
+    }
+
+    // Called after every completed iteration; returns true if we shouldn't start another one
+    pub fn iteration_done(&mut self, var: &Variation) -> bool {
+        if self.forced {
+            return true;
+        }
+
+        // Unstable root: the best move changed or the score dropped - take more time
+        let best_move = var.move_list.front().copied();
+        if self.best_move.is_some() {
+            // Extensions decay when things are stable
+            self.extension /= 2;
+
+            if best_move != self.best_move {
+                self.extension += BEST_MOVE_CHANGE_EXTENSION;
+            }
+
+            if var.eval < self.best_eval - SCORE_DROP {
+                self.extension += SCORE_DROP_EXTENSION;
+            }
+
+            self.extension = cmp::min(self.extension, MAX_EXTENSION);
+        }
+
+        self.best_move = best_move;
+        self.best_eval = var.eval;
+
+        // The next iteration is likely to take longer than all the previous ones put together,
+        // so don't start it if we are already past half the time we want to spend.
+        self.elapsed() * 2 >= self.optimum()
+    }
+}
@@ -0,0 +1,427 @@
+//! UCI (Universal Chess Interface) Protocol
+
+use crate::evaluation::*;
+use crate::hashtables::*;
+use crate::pgn_parser::*;
+use crate::search::*;
+use crate::state::*;
+use crate::timeman::*;
+use std::cmp;
+use std::io;
+use std::io::prelude::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+pub const ENGINE_NAME: &str = "eroica";
+pub const ENGINE_AUTHOR: &str = "Jayakiran Akurathi";
+
+// Transposition table size (in MB)
+pub const DEFAULT_HASH_MB: usize = 256;
+pub const MAX_HASH_MB: usize = 65536;
+
+// Depth used when "go" doesn't specify any limits
+pub const UCI_DEFAULT_DEPTH: usize = 6;
+
+// Everything that may follow "go" - marks the end of "searchmoves"
+pub const GO_KEYWORDS: [&str; 12] = [
+    "searchmoves",
+    "ponder",
+    "wtime",
+    "btime",
+    "winc",
+    "binc",
+    "movestogo",
+    "depth",
+    "nodes",
+    "mate",
+    "movetime",
+    "infinite",
+];
+
+// Search threads (Lazy SMP)
+pub const DEFAULT_THREADS: usize = 1;
+pub const MAX_THREADS: usize = 256;
+
+// Number of lines to search (and report) in analysis
+pub const MAX_MULTI_PV: usize = 256;
+
+// Everything the search thread needs - handed over to it on "go" and handed back when it's done
+pub struct Engine {
+    pub state: State,
+    pub tt: SharedHashTable<Eval>,
+    pub evaluator: Option<HeuristicEvaluator>, // Kept from one search to the next, for its pawn hash table
+    pub threads: usize,
+    pub multi_pv: usize,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Engine {
+            state: State::new(),
+            tt: SharedHashTable::with_mb(DEFAULT_HASH_MB),
+            evaluator: Some(HeuristicEvaluator::new()),
+            threads: DEFAULT_THREADS,
+            multi_pv: 1,
+        }
+    }
+}
+
+// Everything "go" may specify; searchmoves are resolved against the position only when the search starts
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GoArgs<'a> {
+    pub limits: SearchLimits,
+    pub searchmoves: Vec<&'a str>,
+    pub infinite: bool,
+    pub tc: TimeControl,
+}
+
+impl<'a> GoArgs<'a> {
+    pub fn parse(args: &[&'a str]) -> Self {
+        let mut go = GoArgs::default();
+
+        let mut iter = args.iter().peekable();
+        while let Some(&arg) = iter.next() {
+            match arg {
+                "infinite" => {
+                    go.infinite = true;
+                    continue;
+                }
+                // We don't offer the Ponder option, so just search as usual
+                "ponder" => continue,
+                "searchmoves" => {
+                    while let Some(mv_str) = iter.next_if(|x| !GO_KEYWORDS.contains(x)) {
+                        go.searchmoves.push(mv_str);
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            let value = match iter.next().map(|x| x.parse::<u64>()) {
+                Some(Ok(value)) => value,
+                _ => continue,
+            };
+
+            match arg {
+                "depth" => go.limits.depth = Some(cmp::max(value as usize, 1)),
+                "nodes" => go.limits.nodes = Some(value),
+                "mate" => go.limits.mate = Some(cmp::max(value as usize, 1)),
+                "movetime" => go.limits.movetime = Some(value),
+                "wtime" => go.tc.wtime = Some(value),
+                "btime" => go.tc.btime = Some(value),
+                "winc" => go.tc.winc = value,
+                "binc" => go.tc.binc = value,
+                "movestogo" => go.tc.movestogo = Some(value),
+                _ => {}
+            }
+        }
+
+        go
+    }
+}
+
+pub struct Uci {
+    pub engine: Option<Engine>,
+    pub search: Option<JoinHandle<Engine>>,
+    pub stop: Arc<AtomicBool>,
+}
+
+impl Default for Uci {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Uci {
+    pub fn new() -> Self {
+        Uci {
+            engine: Some(Engine::new()),
+            search: None,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // Wait for the search thread (if any) to finish and take back the Engine
+    pub fn wait(&mut self) {
+        if let Some(handle) = self.search.take() {
+            match handle.join() {
+                Ok(engine) => self.engine = Some(engine),
+                Err(_) => panic!("Search thread panicked!"),
+            }
+        }
+    }
+
+    // Abort the search (if any) and take back the Engine
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.wait();
+    }
+
+    pub fn engine(&mut self) -> &mut Engine {
+        self.wait();
+        self.engine.as_mut().unwrap()
+    }
+
+    // Handles one line of input; returns false on "quit"
+    pub fn command(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        match tokens.first() {
+            Some(&"uci") => {
+                println!("id name {}", ENGINE_NAME);
+                println!("id author {}", ENGINE_AUTHOR);
+                println!(
+                    "option name Hash type spin default {} min 1 max {}",
+                    DEFAULT_HASH_MB, MAX_HASH_MB
+                );
+                println!(
+                    "option name Threads type spin default {} min 1 max {}",
+                    DEFAULT_THREADS, MAX_THREADS
+                );
+                println!(
+                    "option name MultiPV type spin default 1 min 1 max {}",
+                    MAX_MULTI_PV
+                );
+                println!("uciok");
+            }
+            Some(&"isready") => println!("readyok"),
+            Some(&"ucinewgame") => {
+                let engine = self.engine();
+                engine.state = State::new();
+                engine.tt.clear();
+            }
+            Some(&"setoption") => {
+                if let Err(error) = self.setoption(&tokens[1..]) {
+                    println!("info string {}", error);
+                }
+            }
+            Some(&"position") => {
+                if let Err(error) = self.position(&tokens[1..]) {
+                    println!("info string {}", error);
+                }
+            }
+            Some(&"go") => self.go(&tokens[1..]),
+            Some(&"stop") => self.stop(),
+            Some(&"quit") => {
+                self.stop();
+                return false;
+            }
+            Some(&"d") => println!("{}", self.engine().state),
+            _ => {}
+        }
+
+        true
+    }
+
+    // setoption name <id> [ value <x> ]
+    pub fn setoption(&mut self, args: &[&str]) -> Result<(), String> {
+        let value_index = args
+            .iter()
+            .position(|&x| x == "value")
+            .unwrap_or(args.len());
+        let name = args.get(1..value_index).unwrap_or(&[]).join(" ");
+        let value = args.get(value_index + 1..).unwrap_or(&[]).join(" ");
+
+        match name.as_str() {
+            "Hash" => match value.parse::<usize>() {
+                Ok(mb) => {
+                    self.engine().tt.resize(mb.clamp(1, MAX_HASH_MB));
+                    Ok(())
+                }
+                Err(_) => Err(format!("Invalid Hash value: {}", value)),
+            },
+            "Threads" => match value.parse::<usize>() {
+                Ok(threads) => {
+                    self.engine().threads = threads.clamp(1, MAX_THREADS);
+                    Ok(())
+                }
+                Err(_) => Err(format!("Invalid Threads value: {}", value)),
+            },
+            "MultiPV" => match value.parse::<usize>() {
+                Ok(multi_pv) => {
+                    self.engine().multi_pv = multi_pv.clamp(1, MAX_MULTI_PV);
+                    Ok(())
+                }
+                Err(_) => Err(format!("Invalid MultiPV value: {}", value)),
+            },
+            _ => Err(format!("No such option: {}", name)),
+        }
+    }
+
+    // position [ startpos | fen <fen> ] [ moves <move_1> ... <move_n> ]
+    pub fn position(&mut self, args: &[&str]) -> Result<(), String> {
+        let moves_index = args.iter().position(|&x| x == "moves");
+        let (setup, moves) = match moves_index {
+            Some(index) => (&args[..index], &args[index + 1..]),
+            None => (args, &args[args.len()..]),
+        };
+
+        let mut state = match setup.first() {
+            Some(&"startpos") => State::new(),
+            Some(&"fen") => State::try_from_fen(&setup[1..].join(" "))
+                .map_err(|error| format!("Invalid FEN: {}", error))?,
+            _ => return Err(format!("Invalid position: {}", args.join(" "))),
+        };
+
+        for mv_str in moves {
+            let mv = parse_long_algebraic(mv_str, &state)?;
+            state.make(&mv);
+        }
+
+        self.engine().state = state;
+        Ok(())
+    }
+
+    // go [ searchmoves <move_1> ... <move_n> ] [ ponder ] [ wtime <x> ] [ btime <x> ] [ winc <x> ] [ binc <x> ]
+    //    [ movestogo <x> ] [ depth <x> ] [ nodes <x> ] [ mate <x> ] [ movetime <x> ] [ infinite ]
+    pub fn go(&mut self, args: &[&str]) {
+        let GoArgs {
+            mut limits,
+            searchmoves,
+            infinite,
+            tc,
+        } = GoArgs::parse(args);
+
+        self.wait();
+        let mut engine = self.engine.take().unwrap();
+
+        for mv_str in searchmoves {
+            match parse_long_algebraic(mv_str, &engine.state) {
+                Ok(mv) => limits.searchmoves.push(mv.compact()),
+                Err(error) => println!("info string {}", error),
+            }
+        }
+
+        // Manage time only if we have a clock, and aren't asked to search infinitely
+        let time = if tc.is_set() && !infinite {
+            Some(TimeManager::new(&tc, &engine.state))
+        } else {
+            None
+        };
+
+        // Don't search forever, unless asked to
+        if !infinite && time.is_none() && !limits.is_bounded() {
+            limits.depth = Some(UCI_DEFAULT_DEPTH);
+        }
+
+        self.stop.store(false, Ordering::Relaxed);
+        let stop = self.stop.clone();
+        self.search = Some(thread::spawn(move || {
+            search(&mut engine, limits, stop, time);
+            engine
+        }));
+    }
+}
+
+// Search and report the result; returns the best move
+pub fn search(
+    engine: &mut Engine,
+    limits: SearchLimits,
+    stop: Arc<AtomicBool>,
+    time: Option<TimeManager>,
+) -> Option<Move> {
+    let start = Instant::now();
+    let mut ctx = SearchContext::with_evaluator(engine.evaluator.take().unwrap());
+    ctx.limits = limits;
+    ctx.stop = stop;
+    ctx.time = time;
+
+    // "multipv <k>" is only reported in MultiPV mode
+    let num_pv = engine.multi_pv;
+    let info = |depth: usize,
+                k: usize,
+                var: &Variation,
+                ctx: &SearchContext,
+                tt: &SharedHashTable<Eval>| {
+        let elapsed = start.elapsed();
+        let nodes = ctx.total_nodes();
+        let nps = (nodes as f64 / elapsed.as_secs_f64().max(1e-3)) as u64;
+        let multi_pv_str = if num_pv > 1 {
+            format!(" multipv {}", k)
+        } else {
+            String::new()
+        };
+        println!(
+            "info depth {}{} score {} nodes {} nps {} hashfull {} time {} pv {}",
+            depth,
+            multi_pv_str,
+            Score::new(var.eval),
+            nodes,
+            nps,
+            tt.hashfull(),
+            elapsed.as_millis(),
+            var.pv_string()
+        );
+    };
+
+    // MultiPV is single threaded (see multi_pv) - say so, rather than quietly ignore Threads
+    let pv = if num_pv > 1 {
+        if engine.threads > 1 {
+            println!("info string MultiPV searches with a single thread");
+        }
+        let lines = multi_pv(
+            &mut engine.state,
+            num_pv,
+            &mut ctx,
+            &engine.tt,
+            &mut |depth, lines, ctx, tt| {
+                for (index, var) in lines.iter().enumerate() {
+                    info(depth, index + 1, var, ctx, tt);
+                }
+            },
+        );
+        lines.into_iter().next().unwrap()
+    } else {
+        lazy_smp(
+            &mut engine.state,
+            engine.threads,
+            &mut ctx,
+            &engine.tt,
+            &mut |depth, var, ctx, tt| info(depth, 1, var, ctx, tt),
+        )
+    };
+
+    if let Some(reason) = ctx.stop_reason {
+        println!("info string search ended: {}", reason);
+    }
+    engine.evaluator = Some(ctx.evaluator);
+
+    match pv.move_list.front() {
+        Some(mv) => println!("bestmove {}", mv.long_algebraic()),
+        None => println!("bestmove 0000"),
+    }
+    let _ = io::stdout().flush();
+    pv.move_list.front().copied()
+}
+
+// Main loop: read commands from stdin till "quit"
+// The first command has already been read (to pick the protocol), so it's passed in.
+pub fn run(first_command: &str) {
+    let stdin = io::stdin();
+    let mut uci = Uci::new();
+    if !uci.command(first_command) {
+        return;
+    }
+
+    for line in stdin.lock().lines() {
+        match line {
+            Ok(line) => {
+                if !uci.command(line.trim()) {
+                    break;
+                }
+            }
+            Err(error) => panic!("Error: {}", error),
+        }
+    }
+
+    uci.wait();
+}
@@ -2,7 +2,6 @@
 
 use crate::consts::*;
 use std::cmp;
-use std::u64;
 
 // A simple fn to print a BitBoard
 pub fn print_bb(bb: &u64) {
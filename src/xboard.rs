@@ -0,0 +1,410 @@
+//! CECP (Chess Engine Communication Protocol) aka xboard Protocol
+
+use crate::consts::*;
+use crate::pgn_parser::*;
+use crate::search::*;
+use crate::state::*;
+use crate::timeman::*;
+use crate::uci::*;
+use std::io;
+use std::io::prelude::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+// Depth used when neither "sd" nor a clock has been specified
+pub const XBOARD_DEFAULT_DEPTH: usize = 6;
+
+// Engine + the list of moves played (with the IRState required to undo them)
+pub struct Game {
+    pub engine: Engine,
+    pub undo_list: Vec<(Move, IRState)>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Game {
+            engine: Engine::new(),
+            undo_list: Vec::new(),
+        }
+    }
+
+    pub fn make(&mut self, mv: &Move) {
+        let irs = self.engine.state.ir_state();
+        self.engine.state.make(mv);
+        self.undo_list.push((*mv, irs));
+    }
+
+    pub fn unmake(&mut self) -> bool {
+        match self.undo_list.pop() {
+            Some((mv, irs)) => {
+                self.engine.state.unmake(&mv, &irs);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Returns the result string if the game is over
+    pub fn result(&self) -> Option<&'static str> {
+        let (_, status) = self.engine.state.node_info();
+        let winner = self.engine.state.to_move ^ COLOR;
+
+        match status {
+            Status::Checkmate => Some(if winner == WHITE {
+                "1-0 {White mates}"
+            } else {
+                "0-1 {Black mates}"
+            }),
+            Status::Stalemate => Some("1/2-1/2 {Stalemate}"),
+            Status::FiftyMoveDraw => Some("1/2-1/2 {Fifty move rule}"),
+            Status::RepetitionDraw => Some("1/2-1/2 {Draw by repetition}"),
+            Status::InsufficientMaterial => Some("1/2-1/2 {Insufficient material}"),
+            Status::Ongoing => None,
+        }
+    }
+}
+
+pub struct Xboard {
+    pub game: Option<Game>,
+    pub search: Option<JoinHandle<Game>>,
+    pub stop: Arc<AtomicBool>, // Stop thinking and play the best move found so far
+    pub abandon: Arc<AtomicBool>, // Stop thinking and don't play any move
+    pub force: bool,
+    pub engine_color: u8,
+    pub post: bool,
+    pub depth: Option<usize>, // As set by "sd"
+
+    // Clock - in centiseconds, as received from the GUI
+    pub moves_per_session: usize,
+    pub base_time: u64,
+    pub increment: u64,
+    pub time_per_move: u64,
+    pub time_left: u64,
+    pub opp_time_left: u64,
+}
+
+impl Default for Xboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Xboard {
+    pub fn new() -> Self {
+        Xboard {
+            game: Some(Game::new()),
+            search: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            abandon: Arc::new(AtomicBool::new(false)),
+            force: false,
+            engine_color: BLACK,
+            post: false,
+            depth: None,
+            moves_per_session: 0,
+            base_time: 0,
+            increment: 0,
+            time_per_move: 0,
+            time_left: 0,
+            opp_time_left: 0,
+        }
+    }
+
+    // Wait for the search thread (if any) to finish and take back the Game
+    pub fn wait(&mut self) {
+        if let Some(handle) = self.search.take() {
+            match handle.join() {
+                Ok(game) => self.game = Some(game),
+                Err(_) => panic!("Search thread panicked!"),
+            }
+        }
+    }
+
+    // Stop thinking (if we are), without playing a move
+    pub fn abandon(&mut self) {
+        self.abandon.store(true, Ordering::Relaxed);
+        self.stop.store(true, Ordering::Relaxed);
+        self.wait();
+    }
+
+    // Any change to the Game abandons the ongoing search
+    pub fn game(&mut self) -> &mut Game {
+        self.abandon();
+        self.game.as_mut().unwrap()
+    }
+
+    // Handles one line of input; returns false on "quit"
+    pub fn command(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let args = tokens.get(1..).unwrap_or(&[]);
+
+        match tokens.first() {
+            Some(&"protover") => {
+                println!(
+                    "feature myname=\"{}\" ping=1 setboard=1 usermove=1 memory=1 smp=1 san=0 sigint=0 sigterm=0 colors=0 done=1",
+                    ENGINE_NAME
+                );
+            }
+            Some(&"new") => {
+                let game = self.game();
+                game.engine.state = State::new();
+                game.engine.tt.clear();
+                game.undo_list.clear();
+                self.force = false;
+                self.engine_color = BLACK;
+                self.depth = None;
+            }
+            Some(&"force") | Some(&"result") => {
+                self.abandon();
+                self.force = true;
+            }
+            Some(&"?") => {
+                self.stop.store(true, Ordering::Relaxed);
+                self.wait();
+            }
+            Some(&"go") => {
+                self.force = false;
+                self.engine_color = self.game().engine.state.to_move;
+                self.think();
+            }
+            Some(&"usermove") => match args.first() {
+                Some(mv_str) => self.usermove(mv_str),
+                None => println!("Error (no move given): usermove"),
+            },
+            Some(&"setboard") => {
+                // Keep the current position on a malformed FEN
+                match State::try_from_fen(&args.join(" ")) {
+                    Ok(state) => {
+                        let game = self.game();
+                        game.engine.state = state;
+                        game.undo_list.clear();
+                    }
+                    Err(_) => println!("tellusererror Illegal position"),
+                }
+            }
+            Some(&"undo") => {
+                self.game().unmake();
+            }
+            Some(&"remove") => {
+                let game = self.game();
+                if game.unmake() {
+                    game.unmake();
+                }
+            }
+            Some(&"level") => self.level(args),
+            Some(&"st") => {
+                if let Some(Ok(seconds)) = args.first().map(|x| x.parse::<u64>()) {
+                    self.time_per_move = seconds * 100;
+                }
+            }
+            Some(&"sd") => {
+                if let Some(Ok(depth)) = args.first().map(|x| x.parse::<usize>()) {
+                    self.depth = Some(depth.max(1));
+                }
+            }
+            Some(&"time") => {
+                if let Some(Ok(time)) = args.first().map(|x| x.parse::<u64>()) {
+                    self.time_left = time;
+                }
+            }
+            Some(&"otim") => {
+                if let Some(Ok(time)) = args.first().map(|x| x.parse::<u64>()) {
+                    self.opp_time_left = time;
+                }
+            }
+            Some(&"memory") => {
+                if let Some(Ok(mb)) = args.first().map(|x| x.parse::<usize>()) {
+                    self.game().engine.tt.resize(mb.clamp(1, MAX_HASH_MB));
+                }
+            }
+            Some(&"cores") => {
+                if let Some(Ok(cores)) = args.first().map(|x| x.parse::<usize>()) {
+                    self.game().engine.threads = cores.clamp(1, MAX_THREADS);
+                }
+            }
+            Some(&"post") => self.post = true,
+            Some(&"nopost") => self.post = false,
+            Some(&"ping") => println!("pong {}", args.first().unwrap_or(&"")),
+            Some(&"quit") => {
+                self.abandon();
+                return false;
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    // level MPS BASE INC; BASE is either minutes or minutes:seconds
+    pub fn level(&mut self, args: &[&str]) {
+        if args.len() != 3 {
+            println!(
+                "Error (wrong number of arguments): level {}",
+                args.join(" ")
+            );
+            return;
+        }
+
+        let mut base = args[1].split(':').map(|x| x.parse::<u64>().unwrap_or(0));
+        let minutes = base.next().unwrap_or(0);
+        let seconds = base.next().unwrap_or(0);
+
+        self.moves_per_session = args[0].parse::<usize>().unwrap_or(0);
+        self.base_time = (minutes * 60 + seconds) * 100;
+        self.increment = (args[2].parse::<f64>().unwrap_or(0.0) * 100.0) as u64;
+        self.time_per_move = 0;
+    }
+
+    // Accepts moves in both coordinate notation and SAN
+    pub fn usermove(&mut self, mv_str: &str) {
+        let game = self.game();
+        let state = &game.engine.state;
+        let mv = match parse_long_algebraic(mv_str, state).or_else(|_| parse_move(mv_str, state)) {
+            Ok(mv) => mv,
+            Err(_) => {
+                println!("Illegal move: {}", mv_str);
+                return;
+            }
+        };
+
+        game.make(&mv);
+        if let Some(result) = game.result() {
+            println!("{}", result);
+        } else if !self.force && self.game().engine.state.to_move == self.engine_color {
+            self.think();
+        }
+    }
+
+    // Clock info in the form the TimeManager expects (milliseconds)
+    pub fn time_control(&self, state: &State) -> TimeControl {
+        let mut tc = TimeControl::default();
+
+        if self.time_per_move == 0 && self.time_left > 0 {
+            let (time, inc) = if state.to_move == WHITE {
+                (&mut tc.wtime, &mut tc.winc)
+            } else {
+                (&mut tc.btime, &mut tc.binc)
+            };
+            *time = Some(self.time_left * 10);
+            *inc = self.increment * 10;
+
+            if self.moves_per_session > 0 {
+                let mps = self.moves_per_session;
+                tc.movestogo = Some((mps - (state.fullmove_count - 1) % mps) as u64);
+            }
+        }
+
+        tc
+    }
+
+    pub fn think(&mut self) {
+        self.abandon();
+        let mut game = self.game.take().unwrap();
+        let tc = self.time_control(&game.engine.state);
+        let time = if tc.is_set() {
+            Some(TimeManager::new(&tc, &game.engine.state))
+        } else {
+            None
+        };
+        let mut limits = SearchLimits::new();
+        limits.depth = self.depth;
+        if self.time_per_move > 0 {
+            limits.movetime = Some(self.time_per_move * 10);
+        }
+        if time.is_none() && !limits.is_bounded() {
+            limits.depth = Some(XBOARD_DEFAULT_DEPTH);
+        }
+        let post = self.post;
+        self.stop.store(false, Ordering::Relaxed);
+        self.abandon.store(false, Ordering::Relaxed);
+        let stop = self.stop.clone();
+        let abandon = self.abandon.clone();
+        self.search = Some(thread::spawn(move || {
+            search(&mut game, limits, time, post, stop, abandon);
+            game
+        }));
+    }
+}
+
+// Search, play the best move and report it
+pub fn search(
+    game: &mut Game,
+    limits: SearchLimits,
+    time: Option<TimeManager>,
+    post: bool,
+    stop: Arc<AtomicBool>,
+    abandon: Arc<AtomicBool>,
+) {
+    let engine = &mut game.engine;
+    let start = Instant::now();
+    let mut ctx = SearchContext::with_evaluator(engine.evaluator.take().unwrap());
+    ctx.limits = limits;
+    ctx.stop = stop;
+    ctx.time = time;
+
+    let pv = lazy_smp(
+        &mut engine.state,
+        engine.threads,
+        &mut ctx,
+        &engine.tt,
+        &mut |depth, var, ctx, _| {
+            if post {
+                // Mate in N is posted as 100000 + N (and getting mated as -100000 - N)
+                let score = match Score::new(var.eval) {
+                    Score::Cp(cp) => cp,
+                    Score::Mate(moves) => moves.signum() * 100000 + moves,
+                };
+                println!(
+                    "{} {} {} {} {}",
+                    depth,
+                    score,
+                    start.elapsed().as_millis() / 10,
+                    ctx.total_nodes(),
+                    var.pv_string()
+                );
+            }
+        },
+    );
+
+    engine.evaluator = Some(ctx.evaluator);
+
+    if abandon.load(Ordering::Relaxed) {
+        return;
+    }
+
+    if let Some(mv) = pv.move_list.front() {
+        println!("move {}", mv.long_algebraic());
+        game.make(mv);
+        if let Some(result) = game.result() {
+            println!("{}", result);
+        }
+    }
+    let _ = io::stdout().flush();
+}
+
+// Main loop: read commands from stdin till "quit"
+pub fn run() {
+    let stdin = io::stdin();
+    let mut xboard = Xboard::new();
+
+    for line in stdin.lock().lines() {
+        match line {
+            Ok(line) => {
+                if !xboard.command(line.trim()) {
+                    break;
+                }
+            }
+            Err(error) => panic!("Error: {}", error),
+        }
+    }
+
+    xboard.wait();
+}